@@ -0,0 +1,384 @@
+//! A broadcast node gossiping messages over a Plumtree broadcast tree.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::message::{MaelstromPayload, Message, MessageBody, MessageId};
+use common::node::{Node, NodeId};
+use common::outbox::Outbox;
+
+mod value;
+
+pub use value::{Value, Verbatim};
+
+/// How long to wait between retransmissions of an unacknowledged gossip message
+const RETRY_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// How long to wait for a message announced via `ihave` before grafting the
+/// announcer back into the eager push tree
+const MISSING_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, MaelstromPayload)]
+pub enum MessagePayload {
+    Broadcast {
+        message: Value,
+    },
+    BroadcastOk,
+    Read,
+    ReadOk {
+        messages: Vec<Value>,
+    },
+    Topology {
+        topology: HashMap<String, Vec<String>>,
+    },
+    TopologyOk,
+    /// Eager push of a message along the broadcast tree
+    Gossip {
+        message: Value,
+    },
+    GossipOk,
+    /// Lazy push announcing messages the sender has delivered. Announcements
+    /// are retransmitted until acknowledged, as a lost one could leave the
+    /// receiver without any way to learn of the messages.
+    #[serde(rename = "ihave")]
+    IHave {
+        messages: Vec<Value>,
+    },
+    #[serde(rename = "ihave_ok")]
+    IHaveOk,
+    /// Requests that the receiver move the sender into its eager push peers and
+    /// send it `message`
+    Graft {
+        message: Value,
+    },
+    /// Requests that the receiver move the sender into its lazy push peers
+    Prune,
+}
+
+/// A broadcast node implementing the Plumtree protocol described in
+/// "Epidemic Broadcast Trees" (Leitão, Pereira, Rodrigues).
+///
+/// Messages are eagerly pushed to `eager_peers`, which form a spanning tree
+/// over the topology, while `lazy_peers` only receive `ihave` announcements.
+/// Receiving a duplicate prunes the redundant edge out of the tree, and an
+/// announcement for a message that does not arrive in time grafts the
+/// announcer back into it.
+#[derive(Debug, Clone)]
+pub struct BroadcastNode {
+    id: NodeId,
+    /// Every message seen, in the order it was first seen
+    seen: Vec<Value>,
+    /// The messages in `seen`, for constant time lookups
+    seen_set: HashSet<Value>,
+    /// The peer each seen message was first received from, if any
+    received_from: HashMap<Value, NodeId>,
+    topology: Option<HashMap<NodeId, Vec<NodeId>>>,
+    eager_peers: BTreeSet<NodeId>,
+    lazy_peers: BTreeSet<NodeId>,
+    curr_msg_id: MessageId,
+    tx: Outbox<MessagePayload>,
+    /// Outstanding deliveries, keyed by the message ID of the gossip and
+    /// mapped to the neighbor it was sent to
+    unacked_messages: Arc<Mutex<HashMap<MessageId, NodeId>>>,
+    /// Messages announced via `ihave` but not yet received, mapped to the
+    /// peers that announced them
+    missing: Arc<Mutex<HashMap<Value, Vec<NodeId>>>>,
+}
+
+impl BroadcastNode {
+    /// Returns the neighbors of this node according to `topology`. Peers are
+    /// kept in ordered sets so that messages are sent in the same order on
+    /// every run, which keeps simulations replayable.
+    fn neighbors_in(&self, topology: &HashMap<NodeId, Vec<NodeId>>) -> BTreeSet<NodeId> {
+        topology
+            .get(&self.id)
+            .expect("unknown node")
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Sends a payload that does not expect a reply
+    fn send(&self, dest: NodeId, payload: MessagePayload) {
+        self.tx.send_or_drop(Message {
+            src: self.id.clone(),
+            dest,
+            body: MessageBody {
+                msg_id: None,
+                in_reply_to: None,
+                payload,
+            },
+        });
+    }
+
+    /// Replies to `request` with `payload`
+    fn reply(&mut self, request: &Message<MessagePayload>, payload: MessagePayload) {
+        let msg_id = self.next_msg_id();
+
+        self.tx.send_or_drop(Message {
+            src: self.id.clone(),
+            dest: request.src.clone(),
+            body: MessageBody {
+                msg_id: Some(msg_id),
+                in_reply_to: request.body.msg_id,
+                payload,
+            },
+        });
+    }
+
+    /// Eagerly pushes `msg` to `neighbor`
+    fn deliver(&mut self, neighbor: NodeId, msg: Value) {
+        self.send_reliably(neighbor, MessagePayload::Gossip { message: msg });
+    }
+
+    /// Repeatedly sends `payload` to `neighbor` until it is acknowledged or
+    /// the delivery is cancelled
+    fn send_reliably(&mut self, neighbor: NodeId, payload: MessagePayload) {
+        let msg_id = self.next_msg_id();
+        let tx = self.tx.clone();
+        let node_id = self.id.clone();
+        let unacked_msgs = Arc::clone(&self.unacked_messages);
+        {
+            unacked_msgs
+                .lock()
+                .expect("poisoned lock")
+                .insert(msg_id, neighbor.clone());
+        }
+
+        tokio::spawn(async move {
+            loop {
+                if !unacked_msgs
+                    .lock()
+                    .expect("poisoned lock")
+                    .contains_key(&msg_id)
+                {
+                    break;
+                }
+                // An attempt rejected by a full outbox is retried like a lost one
+                tx.send_or_drop(Message {
+                    src: node_id.clone(),
+                    dest: neighbor.clone(),
+                    body: MessageBody {
+                        msg_id: Some(msg_id),
+                        in_reply_to: None,
+                        payload: payload.clone(),
+                    },
+                });
+
+                tokio::time::sleep(RETRY_INTERVAL).await;
+            }
+        });
+    }
+
+    /// Delivers a message seen for the first time, eagerly pushing it to the
+    /// tree and lazily announcing it to every other peer
+    fn disseminate(&mut self, msg: Value, sender: Option<NodeId>) {
+        self.seen.push(msg.clone());
+        self.seen_set.insert(msg.clone());
+        self.missing.lock().expect("poisoned lock").remove(&msg);
+
+        if let Some(sender) = &sender {
+            self.received_from.insert(msg.clone(), sender.clone());
+            self.lazy_peers.remove(sender);
+            self.eager_peers.insert(sender.clone());
+        }
+
+        let is_sender = |peer: &NodeId| Some(peer) == sender.as_ref();
+
+        for peer in self.eager_peers.clone() {
+            if !is_sender(&peer) {
+                self.deliver(peer, msg.clone());
+            }
+        }
+
+        for peer in self.lazy_peers.clone() {
+            if !is_sender(&peer) {
+                self.send_reliably(
+                    peer,
+                    MessagePayload::IHave {
+                        messages: vec![msg.clone()],
+                    },
+                );
+            }
+        }
+    }
+
+    /// Records that `announcer` has `msg`, and starts a timer that grafts
+    /// announcers until the message arrives
+    fn expect_missing(&mut self, msg: Value, announcer: NodeId) {
+        {
+            let mut missing = self.missing.lock().expect("poisoned lock");
+            if let Some(announcers) = missing.get_mut(&msg) {
+                announcers.push(announcer);
+                return;
+            }
+            missing.insert(msg.clone(), vec![announcer]);
+        }
+
+        let tx = self.tx.clone();
+        let node_id = self.id.clone();
+        let missing = Arc::clone(&self.missing);
+
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(MISSING_TIMEOUT).await;
+
+                let announcer = {
+                    let mut missing = missing.lock().expect("poisoned lock");
+                    match missing.get_mut(&msg) {
+                        Some(announcers) => {
+                            // Rotate through announcers so a single unreachable
+                            // peer does not stall recovery
+                            announcers.rotate_left(1);
+                            announcers.last().cloned()
+                        }
+                        None => break,
+                    }
+                };
+
+                if let Some(announcer) = announcer {
+                    // A graft rejected by a full outbox is retried on the next
+                    // timeout
+                    tx.send_or_drop(Message {
+                        src: node_id.clone(),
+                        dest: announcer,
+                        body: MessageBody {
+                            msg_id: None,
+                            in_reply_to: None,
+                            payload: MessagePayload::Graft {
+                                message: msg.clone(),
+                            },
+                        },
+                    });
+                }
+            }
+        });
+    }
+
+    /// Reconciles in-flight deliveries with a new topology. Deliveries to nodes
+    /// that are no longer neighbors are cancelled, and every value seen so far is
+    /// delivered to nodes that have just become neighbors.
+    fn apply_topology(&mut self, topology: HashMap<NodeId, Vec<NodeId>>) {
+        let new_neighbors = self.neighbors_in(&topology);
+        let old_neighbors = self
+            .topology
+            .as_ref()
+            .map(|topology| self.neighbors_in(topology))
+            .unwrap_or_default();
+
+        self.unacked_messages
+            .lock()
+            .expect("poisoned lock")
+            .retain(|_, neighbor| new_neighbors.contains(neighbor));
+        self.eager_peers
+            .retain(|neighbor| new_neighbors.contains(neighbor));
+        self.lazy_peers
+            .retain(|neighbor| new_neighbors.contains(neighbor));
+
+        for neighbor in new_neighbors.difference(&old_neighbors) {
+            self.eager_peers.insert(neighbor.clone());
+
+            for msg in self.seen.clone() {
+                self.deliver(neighbor.clone(), msg);
+            }
+        }
+
+        self.topology = Some(topology);
+    }
+}
+
+impl<'de> Node<'de> for BroadcastNode {
+    type Payload = MessagePayload;
+    type Output = MessagePayload;
+
+    fn handle_message(&mut self, message: Message<Self::Payload>) {
+        if self.id != message.dest {
+            return;
+        }
+
+        match &message.body.payload {
+            MessagePayload::Broadcast { message: msg } => {
+                if !self.seen_set.contains(msg) {
+                    self.disseminate(msg.clone(), None);
+                }
+
+                self.reply(&message, MessagePayload::BroadcastOk);
+            }
+            MessagePayload::Gossip { message: msg } => {
+                if !self.seen_set.contains(msg) {
+                    self.disseminate(msg.clone(), Some(message.src.clone()));
+                } else if self.received_from.get(msg) != Some(&message.src) {
+                    // A retransmission from the peer we first received this
+                    // message from is not a redundant tree edge
+                    self.eager_peers.remove(&message.src);
+                    self.lazy_peers.insert(message.src.clone());
+                    self.send(message.src.clone(), MessagePayload::Prune);
+                }
+
+                self.reply(&message, MessagePayload::GossipOk);
+            }
+            MessagePayload::IHave { messages } => {
+                for msg in messages {
+                    if !self.seen_set.contains(msg) {
+                        self.expect_missing(msg.clone(), message.src.clone());
+                    }
+                }
+
+                self.reply(&message, MessagePayload::IHaveOk);
+            }
+            MessagePayload::Graft { message: msg } => {
+                self.lazy_peers.remove(&message.src);
+                self.eager_peers.insert(message.src.clone());
+
+                if self.seen_set.contains(msg) {
+                    self.deliver(message.src.clone(), msg.clone());
+                }
+            }
+            MessagePayload::Prune => {
+                self.eager_peers.remove(&message.src);
+                self.lazy_peers.insert(message.src.clone());
+            }
+            MessagePayload::Topology { topology } => {
+                self.apply_topology(topology.clone());
+                self.reply(&message, MessagePayload::TopologyOk);
+            }
+            MessagePayload::Read => {
+                let messages = self.seen.clone();
+                self.reply(&message, MessagePayload::ReadOk { messages });
+            }
+            MessagePayload::GossipOk | MessagePayload::IHaveOk => {
+                if let Some(in_reply_to) = &message.body.in_reply_to {
+                    self.unacked_messages
+                        .lock()
+                        .expect("poisoned lock")
+                        .remove(in_reply_to);
+                }
+            }
+            MessagePayload::BroadcastOk
+            | MessagePayload::ReadOk { .. }
+            | MessagePayload::TopologyOk => {}
+        }
+    }
+
+    fn from_init(node_id: NodeId, _neighbors: Vec<NodeId>, tx: Outbox<Self::Output>) -> Self {
+        Self {
+            id: node_id,
+            seen: vec![],
+            seen_set: HashSet::new(),
+            received_from: HashMap::new(),
+            topology: None,
+            eager_peers: BTreeSet::new(),
+            lazy_peers: BTreeSet::new(),
+            curr_msg_id: Default::default(),
+            unacked_messages: Arc::new(Mutex::new(HashMap::new())),
+            missing: Arc::new(Mutex::new(HashMap::new())),
+            tx,
+        }
+    }
+
+    fn next_msg_id(&mut self) -> MessageId {
+        self.curr_msg_id = self.curr_msg_id.checked_add(1).expect("id overflow");
+        self.curr_msg_id
+    }
+}
@@ -0,0 +1,159 @@
+//! The values carried by broadcasts, kept exactly as clients wrote them.
+
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+
+use common::codec::Codec;
+use common::middleware::{Action, Envelope, Layer};
+use serde::de::{self, Deserializer};
+use serde::ser::{SerializeMap, Serializer};
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+
+/// The key of the object a value is carried in as text, wherever it cannot
+/// be embedded as written: in binary codecs, and in messages read through
+/// [`Verbatim`]
+const TEXT_KEY: &str = "$broadcast::value";
+
+/// An arbitrary JSON value carried by a broadcast.
+///
+/// Values are held, hashed and compared as their canonical text, minified
+/// and with object keys sorted. Numbers are kept as written, however wide,
+/// as long as messages are read through [`Verbatim`]. Otherwise, numbers
+/// wider than 64 bits are rounded when their message is decoded.
+#[derive(Debug, Clone)]
+pub struct Value(Box<RawValue>);
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.get() == other.0.get()
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.get().hash(state);
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // Only JSON can embed the value as it is
+        if serializer.is_human_readable() {
+            return self.0.serialize(serializer);
+        }
+
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry(TEXT_KEY, self.0.get())?;
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = match serde_json::Value::deserialize(deserializer)? {
+            serde_json::Value::Object(mut map) if map.len() == 1 && map.contains_key(TEXT_KEY) => {
+                match map.remove(TEXT_KEY) {
+                    Some(serde_json::Value::String(text)) => text,
+                    _ => return Err(de::Error::custom("value text is not a string")),
+                }
+            }
+            value => value.to_string(),
+        };
+
+        let raw = RawValue::from_string(text).map_err(de::Error::custom)?;
+        Ok(Self(canonical(&raw)))
+    }
+}
+
+/// `raw` minified, with object keys sorted
+fn canonical(raw: &RawValue) -> Box<RawValue> {
+    const VALID: &str = "raw values are valid JSON";
+
+    let text = raw.get().trim();
+    let canonical = match text.as_bytes().first() {
+        Some(b'{') => {
+            let entries: BTreeMap<String, &RawValue> = serde_json::from_str(text).expect(VALID);
+            let entries: BTreeMap<_, _> = entries
+                .into_iter()
+                .map(|(key, value)| (key, canonical(value)))
+                .collect();
+            serde_json::to_string(&entries).expect(VALID)
+        }
+        Some(b'[') => {
+            let items: Vec<&RawValue> = serde_json::from_str(text).expect(VALID);
+            let items: Vec<_> = items.into_iter().map(canonical).collect();
+            serde_json::to_string(&items).expect(VALID)
+        }
+        Some(b'"') => {
+            let string: String = serde_json::from_str(text).expect(VALID);
+            serde_json::to_string(&string).expect(VALID)
+        }
+        // Numbers and literals
+        _ => text.to_string(),
+    };
+
+    RawValue::from_string(canonical).expect(VALID)
+}
+
+/// Reads the values of broadcasts in JSON frames as they were written,
+/// before decoding the frames rounds numbers wider than 64 bits.
+///
+/// The values are rewritten to be carried as text, which [`Value`] reads
+/// back as it is.
+#[derive(Debug, Default)]
+pub struct Verbatim;
+
+/// The fields of a JSON frame that may hold values
+#[derive(Deserialize)]
+struct Frame<'a> {
+    #[serde(borrow)]
+    body: Body<'a>,
+}
+
+#[derive(Deserialize)]
+struct Body<'a> {
+    #[serde(borrow)]
+    message: Option<&'a RawValue>,
+    #[serde(borrow)]
+    messages: Option<Vec<&'a RawValue>>,
+}
+
+/// `raw` carried as text
+fn as_text(raw: &RawValue) -> serde_json::Value {
+    serde_json::json!({ TEXT_KEY: canonical(raw).get() })
+}
+
+impl Layer for Verbatim {
+    fn inbound(&self, mut envelope: Envelope) -> Action {
+        // Frames that fail to decode are left for the runtime to report
+        let values = envelope
+            .frame()
+            .filter(|frame| Codec::of(frame) == Codec::Json)
+            .and_then(|frame| serde_json::from_slice::<Frame>(frame).ok())
+            .map(|Frame { body }| {
+                let message = body.message.map(as_text);
+                let messages = body
+                    .messages
+                    .map(|messages| messages.into_iter().map(as_text).collect());
+                (message, messages)
+            });
+
+        let (message, messages) = match values {
+            Some((None, None)) | None => return Action::Pass(envelope),
+            Some(values) => values,
+        };
+
+        let payload = &mut envelope.message_mut().body.payload;
+        if let Some(message) = message {
+            payload["message"] = message;
+        }
+        if let Some(messages) = messages {
+            payload["messages"] = serde_json::Value::Array(messages);
+        }
+
+        Action::Pass(envelope)
+    }
+}
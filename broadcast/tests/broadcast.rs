@@ -0,0 +1,304 @@
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use broadcast::{BroadcastNode, Verbatim};
+use common::message::{Message, MessageBody, MessageId};
+use common::node::{Node, NodeId};
+use common::outbox::Outbox;
+use common::runtime::Runtime;
+use common::sim::{Cluster, Faults, Partition, Simulation};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+/// The node ID the cluster's client sends requests as
+const CLIENT: &str = "c0";
+
+/// Stands in for a peer of the broadcast nodes. Every message it receives is
+/// reported to the client as an `observed` message, and the client can have
+/// it send any message with `inject`.
+struct Spy {
+    id: NodeId,
+    curr_msg_id: MessageId,
+    tx: Outbox<Value>,
+}
+
+impl Spy {
+    fn send(&mut self, src: NodeId, dest: NodeId, in_reply_to: Option<MessageId>, payload: Value) {
+        let msg_id = self.next_msg_id();
+        self.tx
+            .send(Message {
+                src,
+                dest,
+                body: MessageBody {
+                    msg_id: Some(msg_id),
+                    in_reply_to,
+                    payload,
+                },
+            })
+            .expect("failed sending message");
+    }
+}
+
+impl<'de> Node<'de> for Spy {
+    type Payload = Value;
+    type Output = Value;
+
+    fn handle_message(&mut self, message: Message<Self::Payload>) {
+        let payload = message.body.payload;
+
+        if payload["type"] == "inject" {
+            // Messages may be sent as another node, to stand in for it
+            let src = payload["as"].as_str().unwrap_or(&self.id).to_string();
+            let dest = payload["to"]
+                .as_str()
+                .expect("inject without to")
+                .to_string();
+            self.send(src, dest, None, payload["message"].clone());
+
+            let id = self.id.clone();
+            self.send(
+                id,
+                message.src,
+                message.body.msg_id,
+                json!({ "type": "inject_ok" }),
+            );
+        } else {
+            let observed = json!({
+                "type": "observed",
+                "from": message.src,
+                "at": self.id,
+                "message": payload,
+            });
+            self.send(self.id.clone(), CLIENT.to_string(), None, observed);
+        }
+    }
+
+    fn from_init(node_id: NodeId, _neighbors: Vec<NodeId>, tx: Outbox<Self::Output>) -> Self {
+        Self {
+            id: node_id,
+            curr_msg_id: Default::default(),
+            tx,
+        }
+    }
+
+    fn next_msg_id(&mut self) -> MessageId {
+        self.curr_msg_id = self.curr_msg_id.checked_add(1).expect("id overflow");
+        self.curr_msg_id
+    }
+}
+
+/// Sends every node its neighbors in `topology`
+async fn set_topology(cluster: &mut Cluster, topology: Value) {
+    for node in topology.as_object().expect("topology").keys() {
+        let _: Value = cluster
+            .request(node, json!({ "type": "topology", "topology": topology }))
+            .await;
+    }
+}
+
+async fn broadcast(cluster: &mut Cluster, node: &str, message: Value) {
+    let _: Value = cluster
+        .request(node, json!({ "type": "broadcast", "message": message }))
+        .await;
+}
+
+async fn read(cluster: &mut Cluster, node: &str) -> Vec<Value> {
+    let reply: Value = cluster.request(node, json!({ "type": "read" })).await;
+    reply["messages"].as_array().expect("messages").clone()
+}
+
+/// Reads `node` until it has seen `count` messages, returning them
+async fn read_until(cluster: &mut Cluster, node: &str, count: usize) -> Vec<Value> {
+    loop {
+        let messages = read(cluster, node).await;
+
+        if messages.len() >= count {
+            return messages;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+/// Waits for a spy to observe a message of type `message_type`
+async fn observe(cluster: &mut Cluster, message_type: &str) -> Value {
+    loop {
+        let observed = cluster.recv::<Value>().await.body.payload;
+
+        if observed["message"]["type"] == message_type {
+            return observed;
+        }
+    }
+}
+
+#[tokio::test]
+async fn values_are_relayed_as_written_through_the_verbatim_layer() {
+    let (stdin, mut stdin_writer) = tokio::io::duplex(1024);
+    let (stdout, stdout_reader) = tokio::io::duplex(1024);
+    tokio::spawn(
+        Runtime::new()
+            .layer(Verbatim)
+            .run::<BroadcastNode, _, _>(stdin, stdout),
+    );
+
+    // Written out by hand, as `json!` would round the wide numbers
+    let lines = [
+        r#"{"src":"c0","dest":"n0","body":{"type":"init","msg_id":1,"node_id":"n0","node_ids":["n0"]}}"#,
+        r#"{"src":"c0","dest":"n0","body":{"type":"topology","msg_id":2,"topology":{"n0":[]}}}"#,
+        r#"{"src":"c0","dest":"n0","body":{"type":"broadcast","msg_id":3,"message":340282366920938463463374607431768211457}}"#,
+        r#"{"src":"c0","dest":"n0","body":{"type":"broadcast","msg_id":4,"message":{ "b": -340282366920938463463374607431768211457, "a": 1.50 }}}"#,
+        r#"{"src":"c0","dest":"n0","body":{"type":"read","msg_id":5}}"#,
+    ];
+    for line in lines {
+        stdin_writer
+            .write_all(format!("{line}\n").as_bytes())
+            .await
+            .unwrap();
+    }
+
+    let mut replies = BufReader::new(stdout_reader).lines();
+    let read_ok = loop {
+        let line = replies.next_line().await.unwrap().unwrap();
+        if line.contains(r#""type":"read_ok""#) {
+            break line;
+        }
+    };
+    assert!(
+        read_ok.contains(
+            r#""messages":[340282366920938463463374607431768211457,{"a":1.50,"b":-340282366920938463463374607431768211457}]"#
+        ),
+        "{read_ok}"
+    );
+}
+
+#[test]
+fn every_node_receives_every_value() {
+    Simulation::from_env().run(|simulation| async move {
+        let mut cluster = simulation.cluster::<BroadcastNode>(5).await;
+        set_topology(
+            &mut cluster,
+            json!({
+                "n0": ["n1", "n2"],
+                "n1": ["n0", "n2", "n3"],
+                "n2": ["n0", "n1", "n4"],
+                "n3": ["n1", "n4"],
+                "n4": ["n2", "n3"],
+            }),
+        )
+        .await;
+        cluster.set_faults(Faults {
+            drop: 0.2,
+            delay: 0.2,
+            reorder: 0.2,
+            max_delay: Duration::from_millis(200),
+            ..Default::default()
+        });
+
+        let values: BTreeSet<u64> = (0..10).collect();
+        for value in &values {
+            broadcast(&mut cluster, &format!("n{}", value % 5), json!(value)).await;
+        }
+
+        for node in cluster.node_ids().to_vec() {
+            let messages = read_until(&mut cluster, &node, values.len()).await;
+            let messages: BTreeSet<_> = messages.iter().filter_map(Value::as_u64).collect();
+            assert_eq!(messages, values);
+        }
+    });
+}
+
+#[test]
+fn duplicate_eager_pushes_are_pruned() {
+    Simulation::from_env().run(|simulation| async move {
+        let mut cluster = simulation.cluster::<BroadcastNode>(1).await;
+        cluster.add_node::<Spy>("s0").await;
+        set_topology(&mut cluster, json!({ "n0": ["s0"] })).await;
+
+        broadcast(&mut cluster, "n0", json!(1)).await;
+        let gossip = observe(&mut cluster, "gossip").await;
+        assert_eq!(gossip["from"], "n0");
+        assert_eq!(gossip["message"]["message"], 1);
+
+        // The spy pushes the value back, which n0 has already seen
+        let _: Value = cluster
+            .request(
+                "s0",
+                json!({ "type": "inject", "to": "n0", "message": { "type": "gossip", "message": 1 } }),
+            )
+            .await;
+
+        let prune = observe(&mut cluster, "prune").await;
+        assert_eq!(prune["from"], "n0");
+    });
+}
+
+#[test]
+fn grafts_repair_the_tree_once_a_partition_heals() {
+    Simulation::from_env().run(|simulation| async move {
+        let mut cluster = simulation.cluster::<BroadcastNode>(2).await;
+        cluster.add_node::<Spy>("s0").await;
+        set_topology(&mut cluster, json!({ "n0": ["n1"], "n1": ["n0"] })).await;
+        let nodes = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+
+        // n0 prunes the edge from n1, leaving n1 to only announce values to it
+        let _: Value = cluster
+            .request(
+                "s0",
+                json!({ "type": "inject", "as": "n0", "to": "n1", "message": { "type": "prune" } }),
+            )
+            .await;
+
+        // n0 learns of the value, but the partition cuts it off before it can
+        // graft it
+        broadcast(&mut cluster, "n1", json!(1)).await;
+        cluster.partition(Partition::components(&[nodes(&["n0"]), nodes(&["n1"])]));
+
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert!(read(&mut cluster, "n0").await.is_empty());
+
+        cluster.heal();
+        assert_eq!(read_until(&mut cluster, "n0", 1).await, [json!(1)]);
+
+        // The graft made the edge eager again, so the next value is pushed to
+        // n0 before n1 acknowledges the broadcast
+        broadcast(&mut cluster, "n1", json!(2)).await;
+        assert_eq!(read(&mut cluster, "n0").await, [json!(1), json!(2)]);
+    });
+}
+
+#[test]
+fn topology_changes_reconcile_deliveries_in_flight() {
+    Simulation::from_env().run(|simulation| async move {
+        let mut cluster = simulation.cluster::<BroadcastNode>(1).await;
+        cluster.add_node::<Spy>("s0").await;
+        cluster.add_node::<Spy>("s1").await;
+        set_topology(&mut cluster, json!({ "n0": ["s0"] })).await;
+
+        // Spies never acknowledge gossip, so n0 keeps retrying the delivery
+        broadcast(&mut cluster, "n0", json!(1)).await;
+        for _ in 0..2 {
+            let gossip = observe(&mut cluster, "gossip").await;
+            assert_eq!(gossip["at"], "s0");
+        }
+
+        let msg_id = cluster.send(
+            "n0",
+            json!({ "type": "topology", "topology": { "n0": ["s1"] } }),
+        );
+        while cluster.recv::<Value>().await.body.in_reply_to != Some(msg_id) {}
+
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        let mut gossip_at = vec![];
+        while let Ok(message) =
+            tokio::time::timeout(Duration::from_millis(1), cluster.recv::<Value>()).await
+        {
+            let observed = message.body.payload;
+            if observed["message"]["type"] == "gossip" {
+                assert_eq!(observed["message"]["message"], 1);
+                gossip_at.push(observed["at"].clone());
+            }
+        }
+
+        assert!(!gossip_at.is_empty());
+        assert!(gossip_at.iter().all(|at| at == "s1"));
+    });
+}
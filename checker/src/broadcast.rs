@@ -0,0 +1,96 @@
+//! Checks that every acknowledged broadcast is eventually delivered to every
+//! node.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use std::collections::BTreeMap;
+
+use crate::history::{operations, Event, HistoryError, Node, Outcome, Process};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "f", rename_all = "snake_case")]
+pub enum BroadcastOp {
+    Broadcast {
+        message: Value,
+    },
+    /// A read, whose `messages` are only known once it completes
+    Read {
+        #[serde(default)]
+        messages: Option<Vec<Value>>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Anomaly {
+    /// An acknowledged broadcast was missing from a node's final read
+    Lost { node: Node, message: Value },
+    /// A read returned a message that was never broadcast
+    Unexpected { process: Process, message: Value },
+    /// A node was never read successfully, so could not be checked
+    MissingFinalRead { node: Node },
+}
+
+/// Checks a broadcast history of a cluster of `nodes`, treating the last
+/// successful read of each node as its final read. Histories should
+/// therefore end with a read on every node, issued after the network has
+/// healed.
+pub fn check(events: &[Event<BroadcastOp>], nodes: &[Node]) -> Result<Vec<Anomaly>, HistoryError> {
+    let operations = operations(events)?;
+    let mut anomalies = vec![];
+
+    let attempted: Vec<_> = operations
+        .iter()
+        .filter_map(|operation| match &operation.invocation {
+            BroadcastOp::Broadcast { message } => Some(message),
+            BroadcastOp::Read { .. } => None,
+        })
+        .collect();
+    let acknowledged: Vec<_> = operations
+        .iter()
+        .filter_map(|operation| match &operation.outcome {
+            Outcome::Ok(BroadcastOp::Broadcast { message }) => Some(message),
+            _ => None,
+        })
+        .collect();
+
+    let mut final_reads = BTreeMap::new();
+
+    for operation in &operations {
+        if let Outcome::Ok(BroadcastOp::Read {
+            messages: Some(messages),
+        }) = &operation.outcome
+        {
+            for message in messages {
+                if !attempted.contains(&message) {
+                    anomalies.push(Anomaly::Unexpected {
+                        process: operation.process,
+                        message: message.clone(),
+                    });
+                }
+            }
+
+            if let Some(node) = &operation.node {
+                final_reads.insert(node, messages);
+            }
+        }
+    }
+
+    for node in nodes {
+        let Some(messages) = final_reads.get(node) else {
+            anomalies.push(Anomaly::MissingFinalRead { node: node.clone() });
+            continue;
+        };
+
+        for message in &acknowledged {
+            if !messages.contains(message) {
+                anomalies.push(Anomaly::Lost {
+                    node: node.clone(),
+                    message: (*message).clone(),
+                });
+            }
+        }
+    }
+
+    Ok(anomalies)
+}
@@ -0,0 +1,89 @@
+//! Checks that a grow-only counter's final reads reflect every add.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::history::{operations, Event, HistoryError, Node, Outcome, Process};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "f", rename_all = "snake_case")]
+pub enum CounterOp {
+    Add {
+        delta: u64,
+    },
+    /// A read, whose `value` is only known once it completes
+    Read {
+        #[serde(default)]
+        value: Option<u64>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Anomaly {
+    /// A read returned more than the sum of every add that may have happened
+    TooHigh {
+        process: Process,
+        value: u64,
+        max: u64,
+    },
+    /// A node's final read was missing acknowledged adds
+    TooLow { node: Node, value: u64, min: u64 },
+    /// A node was never read successfully, so could not be checked
+    MissingFinalRead { node: Node },
+}
+
+/// Checks a counter history of a cluster of `nodes`. Every read must be at
+/// most the sum of all adds that may have taken effect, and the last
+/// successful read of each node must also be at least the sum of all
+/// acknowledged adds.
+pub fn check(events: &[Event<CounterOp>], nodes: &[Node]) -> Result<Vec<Anomaly>, HistoryError> {
+    let operations = operations(events)?;
+    let mut anomalies = vec![];
+    let (mut min, mut max) = (0, 0);
+
+    for operation in &operations {
+        if let CounterOp::Add { delta } = operation.invocation {
+            match operation.outcome {
+                Outcome::Ok(_) => {
+                    min += delta;
+                    max += delta;
+                }
+                Outcome::Unknown => max += delta,
+                Outcome::Fail => {}
+            }
+        }
+    }
+
+    let mut final_reads = BTreeMap::new();
+
+    for operation in &operations {
+        if let Outcome::Ok(CounterOp::Read { value: Some(value) }) = operation.outcome {
+            if value > max {
+                anomalies.push(Anomaly::TooHigh {
+                    process: operation.process,
+                    value,
+                    max,
+                });
+            }
+
+            if let Some(node) = &operation.node {
+                final_reads.insert(node, value);
+            }
+        }
+    }
+
+    for node in nodes {
+        match final_reads.get(node) {
+            Some(&value) if value < min => anomalies.push(Anomaly::TooLow {
+                node: node.clone(),
+                value,
+                min,
+            }),
+            Some(_) => {}
+            None => anomalies.push(Anomaly::MissingFinalRead { node: node.clone() }),
+        }
+    }
+
+    Ok(anomalies)
+}
@@ -0,0 +1,153 @@
+use std::io::{self, BufRead};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Identifies the client process that performed an operation. Each process
+/// performs at most one operation at a time.
+pub type Process = u64;
+
+/// Identifies the node an operation was sent to
+pub type Node = String;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    /// The operation was invoked
+    Invoke,
+    /// The operation completed successfully
+    Ok,
+    /// The operation definitely did not take effect
+    Fail,
+    /// The operation may or may not have taken effect, e.g. it timed out
+    Info,
+}
+
+/// A single invocation or completion recorded by a client
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event<O> {
+    pub process: Process,
+    #[serde(rename = "type")]
+    pub kind: EventKind,
+    pub op: O,
+    /// The node the operation was sent to, if it was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node: Option<Node>,
+    /// Nanoseconds since the start of the test
+    pub time: u64,
+}
+
+/// How an invoked operation turned out
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome<O> {
+    Ok(O),
+    Fail,
+    /// The operation may or may not have taken effect
+    Unknown,
+}
+
+/// An invocation paired with its completion
+#[derive(Debug, Clone, PartialEq)]
+pub struct Operation<O> {
+    pub process: Process,
+    /// The node the operation was sent to, if it was recorded
+    pub node: Option<Node>,
+    pub invocation: O,
+    pub outcome: Outcome<O>,
+    /// When the operation was invoked, in nanoseconds since the start of the
+    /// test
+    pub start: u64,
+    /// When the operation completed, or `None` if its outcome is unknown
+    pub end: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HistoryError {
+    /// A process invoked an operation while another was still in flight
+    ConcurrentInvocation { process: Process },
+    /// A process completed an operation it never invoked
+    UnmatchedCompletion { process: Process },
+}
+
+impl std::fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ConcurrentInvocation { process } => {
+                write!(
+                    f,
+                    "process {process} invoked an operation while another was in flight"
+                )
+            }
+            Self::UnmatchedCompletion { process } => {
+                write!(
+                    f,
+                    "process {process} completed an operation it never invoked"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for HistoryError {}
+
+/// Pairs every invocation in `events` with its completion, in invocation
+/// order. Invocations that never complete have an unknown outcome.
+pub fn operations<O: Clone>(events: &[Event<O>]) -> Result<Vec<Operation<O>>, HistoryError> {
+    let mut operations: Vec<Operation<O>> = vec![];
+    // Index into `operations` of each process's in-flight operation
+    let mut in_flight = std::collections::HashMap::new();
+
+    for event in events {
+        let process = event.process;
+
+        match event.kind {
+            EventKind::Invoke => {
+                if in_flight.insert(process, operations.len()).is_some() {
+                    return Err(HistoryError::ConcurrentInvocation { process });
+                }
+
+                operations.push(Operation {
+                    process,
+                    node: event.node.clone(),
+                    invocation: event.op.clone(),
+                    outcome: Outcome::Unknown,
+                    start: event.time,
+                    end: None,
+                });
+            }
+            kind => {
+                let index = in_flight
+                    .remove(&process)
+                    .ok_or(HistoryError::UnmatchedCompletion { process })?;
+                let operation = &mut operations[index];
+
+                match kind {
+                    EventKind::Ok => {
+                        operation.outcome = Outcome::Ok(event.op.clone());
+                        operation.end = Some(event.time);
+                    }
+                    EventKind::Fail => {
+                        operation.outcome = Outcome::Fail;
+                        operation.end = Some(event.time);
+                    }
+                    EventKind::Info | EventKind::Invoke => {}
+                }
+            }
+        }
+    }
+
+    Ok(operations)
+}
+
+/// Reads a history recorded as one JSON event per line
+pub fn read_jsonl<O, R>(reader: R) -> io::Result<Vec<Event<O>>>
+where
+    O: DeserializeOwned,
+    R: BufRead,
+{
+    reader
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
@@ -0,0 +1,8 @@
+//! Checkers validating recorded client histories against the properties each
+//! workload is expected to uphold.
+
+pub mod broadcast;
+pub mod g_counter;
+pub mod history;
+pub mod linearizability;
+pub mod unique_ids;
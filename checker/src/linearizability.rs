@@ -0,0 +1,176 @@
+//! Checks single-register histories for linearizability, using the search
+//! from Wing & Gong with the memoization of visited configurations introduced
+//! by Lowe and used by Knossos.
+
+use std::collections::HashSet;
+use std::hash::Hash;
+
+use serde::{Deserialize, Serialize};
+
+use crate::history::{operations, Event, HistoryError, Operation, Outcome};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "f", rename_all = "snake_case")]
+pub enum RegisterOp<V> {
+    /// A read, whose `value` is only known once it completes. A completed
+    /// read with no value observed an empty register.
+    Read {
+        #[serde(default)]
+        value: Option<V>,
+    },
+    Write {
+        value: V,
+    },
+    Cas {
+        from: V,
+        to: V,
+    },
+}
+
+impl<V: Clone + PartialEq> RegisterOp<V> {
+    /// Applies this operation to a register holding `state`, returning the new
+    /// state, or `None` if the operation could not have returned what it did
+    fn step(&self, state: &Option<V>) -> Option<Option<V>> {
+        match self {
+            Self::Read { value } => (value == state).then(|| state.clone()),
+            Self::Write { value } => Some(Some(value.clone())),
+            Self::Cas { from, to } => (state.as_ref() == Some(from)).then(|| Some(to.clone())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Anomaly<V> {
+    /// No linearization of the history exists. `linearized` is the longest
+    /// valid partial linearization found, in order.
+    NotLinearizable {
+        linearized: Vec<Operation<RegisterOp<V>>>,
+    },
+}
+
+/// An operation that may need to be linearized
+struct Entry<V> {
+    op: RegisterOp<V>,
+    start: u64,
+    end: u64,
+    /// Whether the operation is known to have taken effect, and so must appear
+    /// in the linearization
+    required: bool,
+}
+
+struct Search<'a, V> {
+    entries: &'a [Entry<V>],
+    /// Configurations already explored without success
+    visited: HashSet<(Vec<u64>, Option<V>)>,
+    path: Vec<usize>,
+    longest: Vec<usize>,
+}
+
+impl<V: Clone + Eq + Hash> Search<'_, V> {
+    fn run(&mut self, linearized: &mut Vec<u64>, state: &Option<V>) -> bool {
+        let is_linearized =
+            |linearized: &[u64], i: usize| linearized[i / 64] & (1 << (i % 64)) != 0;
+        let pending = || {
+            self.entries
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !is_linearized(linearized, *i))
+        };
+
+        // Nothing can be linearized after an operation that has returned
+        let horizon = pending()
+            .filter(|(_, entry)| entry.required)
+            .map(|(_, entry)| entry.end)
+            .min();
+
+        let Some(horizon) = horizon else {
+            return true;
+        };
+
+        let candidates: Vec<_> = pending()
+            .filter(|(_, entry)| entry.start < horizon)
+            .map(|(i, _)| i)
+            .collect();
+
+        for i in candidates {
+            let Some(next) = self.entries[i].op.step(state) else {
+                continue;
+            };
+
+            linearized[i / 64] |= 1 << (i % 64);
+
+            if self.visited.insert((linearized.clone(), next.clone())) {
+                self.path.push(i);
+                if self.path.len() > self.longest.len() {
+                    self.longest = self.path.clone();
+                }
+
+                if self.run(linearized, &next) {
+                    return true;
+                }
+                self.path.pop();
+            }
+
+            linearized[i / 64] &= !(1 << (i % 64));
+        }
+
+        false
+    }
+}
+
+/// Checks that a history of operations on a single register, initially empty,
+/// is linearizable. Operations with unknown outcomes may be linearized at any
+/// point after their invocation, or not at all.
+pub fn check<V>(events: &[Event<RegisterOp<V>>]) -> Result<Vec<Anomaly<V>>, HistoryError>
+where
+    V: Clone + Eq + Hash,
+{
+    let operations: Vec<_> = operations(events)?
+        .into_iter()
+        .filter(
+            |operation| match (&operation.outcome, &operation.invocation) {
+                (Outcome::Fail, _) => false,
+                // A read that may not have happened constrains nothing
+                (Outcome::Unknown, RegisterOp::Read { .. }) => false,
+                _ => true,
+            },
+        )
+        .collect();
+
+    let entries: Vec<_> = operations
+        .iter()
+        .map(|operation| {
+            let (op, required) = match &operation.outcome {
+                Outcome::Ok(op) => (op.clone(), true),
+                _ => (operation.invocation.clone(), false),
+            };
+
+            Entry {
+                op,
+                start: operation.start,
+                end: operation.end.unwrap_or(u64::MAX),
+                required,
+            }
+        })
+        .collect();
+
+    let mut search = Search {
+        entries: &entries,
+        visited: HashSet::new(),
+        path: vec![],
+        longest: vec![],
+    };
+    let mut linearized = vec![0; entries.len().div_ceil(64)];
+
+    if search.run(&mut linearized, &None) {
+        return Ok(vec![]);
+    }
+
+    Ok(vec![Anomaly::NotLinearizable {
+        linearized: search
+            .longest
+            .iter()
+            .map(|i| operations[*i].clone())
+            .collect(),
+    }])
+}
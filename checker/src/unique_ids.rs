@@ -0,0 +1,48 @@
+//! Checks that every generated ID is unique.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::history::{operations, Event, HistoryError, Outcome, Process};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "f", rename_all = "snake_case")]
+pub enum GenerateOp {
+    /// A generate, whose `id` is only known once it completes
+    Generate {
+        #[serde(default)]
+        id: Option<Value>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Anomaly {
+    /// The same ID was returned to more than one generate
+    Duplicate { id: Value, processes: Vec<Process> },
+}
+
+pub fn check(events: &[Event<GenerateOp>]) -> Result<Vec<Anomaly>, HistoryError> {
+    // Keyed by serialized ID, since JSON values cannot be hashed
+    let mut generated: HashMap<String, (Value, Vec<Process>)> = HashMap::new();
+
+    for operation in operations(events)? {
+        if let Outcome::Ok(GenerateOp::Generate { id: Some(id) }) = operation.outcome {
+            generated
+                .entry(id.to_string())
+                .or_insert_with(|| (id, vec![]))
+                .1
+                .push(operation.process);
+        }
+    }
+
+    let mut anomalies: Vec<_> = generated
+        .into_values()
+        .filter(|(_, processes)| processes.len() > 1)
+        .map(|(id, processes)| Anomaly::Duplicate { id, processes })
+        .collect();
+    anomalies.sort_by_key(|Anomaly::Duplicate { id, .. }| id.to_string());
+
+    Ok(anomalies)
+}
@@ -0,0 +1,293 @@
+use checker::history::{Event, EventKind};
+use checker::linearizability::RegisterOp;
+use checker::{broadcast, g_counter, linearizability, unique_ids};
+use serde_json::json;
+
+fn event<O>(process: u64, kind: EventKind, op: O, time: u64) -> Event<O> {
+    Event {
+        process,
+        kind,
+        op,
+        node: None,
+        time,
+    }
+}
+
+/// Marks `event` as sent to `node`
+fn on<O>(node: &str, event: Event<O>) -> Event<O> {
+    Event {
+        node: Some(node.to_string()),
+        ..event
+    }
+}
+
+fn nodes(ids: &[&str]) -> Vec<String> {
+    ids.iter().map(|id| id.to_string()).collect()
+}
+
+fn invoke<O>(process: u64, op: O, time: u64) -> Event<O> {
+    event(process, EventKind::Invoke, op, time)
+}
+
+fn ok<O>(process: u64, op: O, time: u64) -> Event<O> {
+    event(process, EventKind::Ok, op, time)
+}
+
+fn read(value: Option<u64>) -> RegisterOp<u64> {
+    RegisterOp::Read { value }
+}
+
+fn write(value: u64) -> RegisterOp<u64> {
+    RegisterOp::Write { value }
+}
+
+#[test]
+fn broadcast_reports_lost_and_unexpected_messages() {
+    use broadcast::BroadcastOp::{Broadcast, Read};
+
+    let history = vec![
+        invoke(0, Broadcast { message: json!(1) }, 0),
+        ok(0, Broadcast { message: json!(1) }, 1),
+        invoke(0, Broadcast { message: json!(2) }, 2),
+        ok(0, Broadcast { message: json!(2) }, 3),
+        on("n0", invoke(1, Read { messages: None }, 4)),
+        on(
+            "n0",
+            ok(
+                1,
+                Read {
+                    messages: Some(vec![json!(1), json!(3)]),
+                },
+                5,
+            ),
+        ),
+        on("n1", invoke(2, Read { messages: None }, 4)),
+        on(
+            "n1",
+            ok(
+                2,
+                Read {
+                    messages: Some(vec![json!(1), json!(2)]),
+                },
+                5,
+            ),
+        ),
+    ];
+
+    assert_eq!(
+        broadcast::check(&history, &nodes(&["n0", "n1"])).unwrap(),
+        [
+            broadcast::Anomaly::Unexpected {
+                process: 1,
+                message: json!(3)
+            },
+            broadcast::Anomaly::Lost {
+                node: "n0".to_string(),
+                message: json!(2)
+            },
+        ]
+    );
+}
+
+#[test]
+fn broadcast_requires_a_final_read_from_every_node() {
+    use broadcast::BroadcastOp::{Broadcast, Read};
+
+    let read = |messages| Read {
+        messages: Some(messages),
+    };
+    // Two processes read n0, and none reads n1
+    let history = vec![
+        invoke(0, Broadcast { message: json!(1) }, 0),
+        ok(0, Broadcast { message: json!(1) }, 1),
+        on("n0", invoke(1, Read { messages: None }, 2)),
+        on("n0", ok(1, read(vec![json!(1)]), 3)),
+        on("n0", invoke(2, Read { messages: None }, 2)),
+        on("n0", ok(2, read(vec![json!(1)]), 3)),
+    ];
+
+    assert_eq!(
+        broadcast::check(&history, &nodes(&["n0", "n1"])).unwrap(),
+        [broadcast::Anomaly::MissingFinalRead {
+            node: "n1".to_string()
+        }]
+    );
+}
+
+#[test]
+fn g_counter_bounds_reads_by_possible_adds() {
+    use g_counter::CounterOp::{Add, Read};
+
+    let history = vec![
+        invoke(0, Add { delta: 2 }, 0),
+        ok(0, Add { delta: 2 }, 1),
+        // Never completes, so may or may not be counted
+        invoke(1, Add { delta: 3 }, 0),
+        on("n0", invoke(2, Read { value: None }, 2)),
+        on("n0", ok(2, Read { value: Some(5) }, 3)),
+        on("n1", invoke(3, Read { value: None }, 2)),
+        on("n1", ok(3, Read { value: Some(1) }, 3)),
+        on("n2", invoke(4, Read { value: None }, 2)),
+        on("n2", ok(4, Read { value: Some(6) }, 3)),
+    ];
+
+    assert_eq!(
+        g_counter::check(&history, &nodes(&["n0", "n1", "n2"])).unwrap(),
+        [
+            g_counter::Anomaly::TooHigh {
+                process: 4,
+                value: 6,
+                max: 5
+            },
+            g_counter::Anomaly::TooLow {
+                node: "n1".to_string(),
+                value: 1,
+                min: 2
+            },
+        ]
+    );
+}
+
+#[test]
+fn g_counter_requires_a_final_read_from_every_node() {
+    use g_counter::CounterOp::{Add, Read};
+
+    // n1's read fails, and the last read of n0 is too low even though an
+    // earlier one was not
+    let history = vec![
+        invoke(0, Add { delta: 2 }, 0),
+        ok(0, Add { delta: 2 }, 1),
+        on("n0", invoke(1, Read { value: None }, 2)),
+        on("n0", ok(1, Read { value: Some(2) }, 3)),
+        on("n0", invoke(2, Read { value: None }, 4)),
+        on("n0", ok(2, Read { value: Some(0) }, 5)),
+        on("n1", invoke(3, Read { value: None }, 4)),
+        on("n1", event(3, EventKind::Fail, Read { value: None }, 5)),
+    ];
+
+    assert_eq!(
+        g_counter::check(&history, &nodes(&["n0", "n1"])).unwrap(),
+        [
+            g_counter::Anomaly::TooLow {
+                node: "n0".to_string(),
+                value: 0,
+                min: 2
+            },
+            g_counter::Anomaly::MissingFinalRead {
+                node: "n1".to_string()
+            },
+        ]
+    );
+}
+
+#[test]
+fn unique_ids_reports_duplicates() {
+    use unique_ids::GenerateOp::Generate;
+
+    let history = vec![
+        invoke(0, Generate { id: None }, 0),
+        ok(
+            0,
+            Generate {
+                id: Some(json!("n0-1")),
+            },
+            1,
+        ),
+        invoke(1, Generate { id: None }, 0),
+        ok(
+            1,
+            Generate {
+                id: Some(json!("n1-1")),
+            },
+            1,
+        ),
+        invoke(2, Generate { id: None }, 0),
+        ok(
+            2,
+            Generate {
+                id: Some(json!("n0-1")),
+            },
+            1,
+        ),
+    ];
+
+    assert_eq!(
+        unique_ids::check(&history).unwrap(),
+        [unique_ids::Anomaly::Duplicate {
+            id: json!("n0-1"),
+            processes: vec![0, 2]
+        }]
+    );
+}
+
+#[test]
+fn concurrent_register_operations_are_linearizable() {
+    // The read overlaps both writes, so may observe either
+    let history = vec![
+        invoke(0, write(1), 0),
+        invoke(1, read(None), 1),
+        ok(0, write(1), 2),
+        invoke(0, write(2), 3),
+        ok(1, read(Some(1)), 4),
+        ok(0, write(2), 5),
+        invoke(1, RegisterOp::Cas { from: 2, to: 3 }, 6),
+        ok(1, RegisterOp::Cas { from: 2, to: 3 }, 7),
+        invoke(0, read(None), 8),
+        ok(0, read(Some(3)), 9),
+    ];
+
+    assert!(linearizability::check(&history).unwrap().is_empty());
+}
+
+#[test]
+fn stale_register_reads_are_not_linearizable() {
+    let history = vec![
+        invoke(0, write(1), 0),
+        ok(0, write(1), 1),
+        invoke(0, write(2), 2),
+        ok(0, write(2), 3),
+        invoke(1, read(None), 4),
+        ok(1, read(Some(1)), 5),
+    ];
+
+    let anomalies = linearizability::check(&history).unwrap();
+    let [linearizability::Anomaly::NotLinearizable { linearized }] = anomalies.as_slice() else {
+        panic!("expected history to be non-linearizable, got {anomalies:?}");
+    };
+
+    assert_eq!(linearized.len(), 2);
+}
+
+#[test]
+fn writes_with_unknown_outcomes_may_take_effect() {
+    let history = vec![
+        invoke(0, write(1), 0),
+        ok(0, write(1), 1),
+        // Times out, but reaches the register
+        invoke(1, write(2), 2),
+        event(1, EventKind::Info, write(2), 3),
+        invoke(2, read(None), 10),
+        ok(2, read(Some(2)), 11),
+        invoke(2, read(None), 12),
+        ok(2, read(Some(2)), 13),
+    ];
+
+    assert!(linearizability::check(&history).unwrap().is_empty());
+}
+
+#[test]
+fn histories_are_read_from_jsonl() {
+    let jsonl = r#"
+{"process": 0, "type": "invoke", "op": {"f": "add", "delta": 1}, "time": 0}
+{"process": 0, "type": "ok", "op": {"f": "add", "delta": 1}, "time": 5}
+{"process": 1, "type": "invoke", "op": {"f": "read"}, "node": "n0", "time": 6}
+{"process": 1, "type": "ok", "op": {"f": "read", "value": 1}, "node": "n0", "time": 7}
+"#;
+
+    let history = checker::history::read_jsonl(jsonl.trim().as_bytes()).unwrap();
+
+    assert_eq!(history.len(), 4);
+    assert!(g_counter::check(&history, &nodes(&["n0"]))
+        .unwrap()
+        .is_empty());
+}
@@ -0,0 +1,97 @@
+//! Compares the runtime's batched writer with writing each message on its own,
+//! as the runtime used to, when a node sends messages faster than they can be
+//! written, and the binary codecs nodes may exchange messages in with JSON.
+//!
+//! Run with `cargo bench -p common --bench writer`.
+
+use std::time::{Duration, Instant};
+
+use common::codec::Codec;
+use common::message::{Message, MessageBody};
+use common::outbox::{self, Overflow, Receiver};
+use common::writer::MessageWriter;
+use serde::Serialize;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+const MESSAGES: u64 = 200_000;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+enum Payload {
+    Gossip { message: u64 },
+}
+
+/// Fills an outbox from another thread, as a node's handlers would
+fn produce() -> Receiver<Payload> {
+    let (tx, rx) = outbox::channel(1024, Overflow::Block);
+
+    std::thread::spawn(move || {
+        for n in 0..MESSAGES {
+            tx.send(Message {
+                src: "n0".to_string(),
+                dest: "n1".to_string(),
+                body: MessageBody {
+                    msg_id: Some(n),
+                    in_reply_to: None,
+                    payload: Payload::Gossip { message: n },
+                },
+            })
+            .expect("failed sending message");
+        }
+    });
+
+    rx
+}
+
+/// Writes each message with its own buffer and write
+async fn write_unbatched<W: AsyncWrite + Unpin>(writer: &mut W, rx: &mut Receiver<Payload>) {
+    while let Some(message) = rx.recv().await {
+        let mut bytes = serde_json::to_vec(&message).expect("failed serializing message");
+        bytes.extend(b"\n");
+        writer.write_all(&bytes).await.expect("failed writing buf");
+    }
+    writer.flush().await.expect("failed flushing writer");
+}
+
+async fn sink() -> tokio::fs::File {
+    tokio::fs::OpenOptions::new()
+        .write(true)
+        .open("/dev/null")
+        .await
+        .expect("failed opening /dev/null")
+}
+
+fn report(name: &str, elapsed: Duration) -> f64 {
+    let rate = MESSAGES as f64 / elapsed.as_secs_f64();
+    println!("{name:>10}: {MESSAGES} messages in {elapsed:.2?} ({rate:.0} msgs/s)");
+    rate
+}
+
+#[tokio::main]
+async fn main() {
+    let mut rx = produce();
+    let mut writer = sink().await;
+    let start = Instant::now();
+    write_unbatched(&mut writer, &mut rx).await;
+    let unbatched = report("unbatched", start.elapsed());
+
+    let mut rx = produce();
+    let mut writer = MessageWriter::new(sink().await);
+    let start = Instant::now();
+    writer.drain(&mut rx).await;
+    let batched = report("batched", start.elapsed());
+
+    println!("{:>10}: {:.1}x", "speedup", batched / unbatched);
+
+    for (name, codec) in [("msgpack", Codec::MessagePack), ("cbor", Codec::Cbor)] {
+        let mut rx = produce();
+        let mut writer = MessageWriter::new(sink().await)
+            .codec(codec)
+            .servers(["n1".to_string()]);
+        let start = Instant::now();
+        writer.drain(&mut rx).await;
+        let rate = report(name, start.elapsed());
+        println!("{:>10}: {:.1}x", "vs json", rate / batched);
+    }
+}
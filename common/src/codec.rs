@@ -0,0 +1,176 @@
+//! Encoding of messages on the wire. Maelstrom and clients only speak
+//! newline-delimited JSON, but nodes may exchange binary frames, which are
+//! cheaper to encode and decode.
+//!
+//! A binary frame is a marker byte naming its codec, followed by the length
+//! of the encoded message as a big-endian `u32` and the message itself.
+//! Neither marker can start a JSON message, so frames of every codec can be
+//! mixed on one stream.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::io;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+use crate::message::Message;
+
+const MESSAGE_PACK_MARKER: u8 = 0x01;
+const CBOR_MARKER: u8 = 0x02;
+
+/// The length of a binary frame's marker and length
+const HEADER_LEN: usize = 5;
+
+/// The most bytes a frame read from a stream may take, so a corrupt or
+/// hostile length cannot exhaust memory
+pub const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    /// Newline-delimited JSON, as spoken by Maelstrom
+    #[default]
+    Json,
+    #[serde(rename = "msgpack")]
+    MessagePack,
+    Cbor,
+}
+
+impl Codec {
+    /// The codec `frame` was encoded with
+    pub fn of(frame: &[u8]) -> Self {
+        match frame.first() {
+            Some(&MESSAGE_PACK_MARKER) => Self::MessagePack,
+            Some(&CBOR_MARKER) => Self::Cbor,
+            _ => Self::Json,
+        }
+    }
+
+    /// Appends `message` to `buf` as a single frame
+    pub fn encode<P: Serialize>(self, message: &Message<P>, buf: &mut Vec<u8>) {
+        match self {
+            Self::Json => {
+                serde_json::to_writer(&mut *buf, message).expect("failed serializing message");
+                buf.push(b'\n');
+            }
+            Self::MessagePack => encode_binary(MESSAGE_PACK_MARKER, buf, |buf| {
+                // Structs must be encoded as maps for their fields to be
+                // flattened and their tags found
+                rmp_serde::encode::write_named(buf, message).expect("failed serializing message")
+            }),
+            Self::Cbor => encode_binary(CBOR_MARKER, buf, |buf| {
+                *buf = cbor4ii::serde::to_vec(std::mem::take(buf), message)
+                    .expect("failed serializing message")
+            }),
+        }
+    }
+}
+
+/// A frame that could not be decoded
+#[derive(Debug)]
+pub struct DecodeError(String);
+
+impl DecodeError {
+    fn new(error: impl fmt::Display) -> Self {
+        Self(error.to_string())
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed decoding frame: {}", self.0)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Decodes `frame`, a frame of any codec, into a `T` that may borrow from it
+pub fn decode<'a, T: Deserialize<'a>>(frame: &'a [u8]) -> Result<T, DecodeError> {
+    let codec = Codec::of(frame);
+    if codec == Codec::Json {
+        return serde_json::from_slice(frame).map_err(DecodeError::new);
+    }
+
+    let body = frame
+        .get(HEADER_LEN..)
+        .ok_or_else(|| DecodeError::new("truncated header"))?;
+    match codec {
+        Codec::MessagePack => rmp_serde::from_slice(body).map_err(DecodeError::new),
+        Codec::Cbor => cbor4ii::serde::from_slice(body).map_err(DecodeError::new),
+        Codec::Json => unreachable!(),
+    }
+}
+
+/// Converts `frame` to JSON without its trailing newline, for tracing. Frames
+/// that cannot be decoded are returned as they are.
+pub fn to_json(frame: &[u8]) -> Cow<'_, [u8]> {
+    if Codec::of(frame) == Codec::Json {
+        return Cow::Borrowed(frame.trim_ascii_end());
+    }
+
+    match decode::<Message<Value>>(frame) {
+        Ok(message) => {
+            Cow::Owned(serde_json::to_vec(&message).expect("failed serializing message"))
+        }
+        Err(_) => Cow::Borrowed(frame),
+    }
+}
+
+/// Reads the next frame of any codec into `frame`, returning `false` at the
+/// end of the stream.
+///
+/// Frames longer than [`MAX_FRAME_LEN`] are rejected with
+/// [`io::ErrorKind::InvalidData`], after which the stream cannot be read
+/// any further.
+pub async fn read_frame<R>(reader: &mut R, frame: &mut Vec<u8>) -> io::Result<bool>
+where
+    R: AsyncBufRead + Unpin,
+{
+    frame.clear();
+    let Some(marker) = reader.fill_buf().await?.first().copied() else {
+        return Ok(false);
+    };
+
+    if matches!(marker, MESSAGE_PACK_MARKER | CBOR_MARKER) {
+        frame.resize(HEADER_LEN, 0);
+        reader.read_exact(frame).await?;
+
+        let len = u32::from_be_bytes(frame[1..HEADER_LEN].try_into().expect("header length"));
+        let len = HEADER_LEN + len as usize;
+        if len > MAX_FRAME_LEN {
+            return Err(frame_too_long(len));
+        }
+        frame.resize(len, 0);
+        reader.read_exact(&mut frame[HEADER_LEN..]).await?;
+    } else {
+        let limit = MAX_FRAME_LEN as u64 + 1;
+        (&mut *reader).take(limit).read_until(b'\n', frame).await?;
+        if frame.len() > MAX_FRAME_LEN {
+            return Err(frame_too_long(frame.len()));
+        }
+    }
+
+    Ok(true)
+}
+
+fn frame_too_long(len: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("frame of {len} bytes exceeds the maximum of {MAX_FRAME_LEN}"),
+    )
+}
+
+/// Appends a binary frame with `marker` to `buf`, its message written by
+/// `encode`
+fn encode_binary(marker: u8, buf: &mut Vec<u8>, encode: impl FnOnce(&mut Vec<u8>)) {
+    let start = buf.len();
+    buf.push(marker);
+    buf.extend([0; HEADER_LEN - 1]);
+
+    encode(buf);
+
+    let len = u32::try_from(buf.len() - start - HEADER_LEN).expect("message too large");
+    buf[start + 1..start + HEADER_LEN].copy_from_slice(&len.to_be_bytes());
+}
@@ -0,0 +1,38 @@
+//! State-based CRDTs (convergent replicated data types).
+//!
+//! Every type here forms a join-semilattice under [`Crdt::merge`], so replicas
+//! that exchange their full state in any order, any number of times, converge
+//! to the same value.
+
+mod delta;
+mod gcounter;
+mod gset;
+mod lww_register;
+mod mv_register;
+mod or_set;
+mod pncounter;
+mod two_phase_set;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+pub use delta::{DeltaReplicator, DeltaSync};
+pub use gcounter::GCounter;
+pub use gset::GSet;
+pub use lww_register::LwwRegister;
+pub use mv_register::MvRegister;
+pub use or_set::OrSet;
+pub use pncounter::PnCounter;
+pub use two_phase_set::TwoPhaseSet;
+
+pub trait Crdt: Clone + Serialize + DeserializeOwned {
+    /// The value observed by reading the replica
+    type Value;
+
+    /// Merges the state of `other` into this replica. Merging must be
+    /// commutative, associative and idempotent.
+    fn merge(&mut self, other: &Self);
+
+    /// Returns the current value of this replica
+    fn value(&self) -> Self::Value;
+}
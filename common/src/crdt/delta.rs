@@ -0,0 +1,148 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use super::Crdt;
+use crate::node::NodeId;
+
+/// A message shipping state from one replica to a peer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeltaSync<C> {
+    /// The sender's delta sequence number at the time of sending. The receiver
+    /// acknowledges it so the sender knows which deltas the receiver has seen.
+    pub seq: u64,
+    /// Whether `state` is the sender's full state rather than a delta-group
+    pub full: bool,
+    pub state: C,
+}
+
+/// Replicates a CRDT by shipping only the deltas each peer has not yet
+/// acknowledged, following the anti-entropy algorithm from "Delta State
+/// Replicated Data Types" (Almeida, Shoker, Baquero).
+///
+/// Deltas are buffered until every peer has acknowledged them. If the buffer
+/// grows past its capacity the oldest deltas are dropped, and peers that have
+/// not acknowledged them are sent the full state instead.
+#[derive(Debug, Clone)]
+pub struct DeltaReplicator<C> {
+    state: C,
+    /// Buffered deltas and the peer each was received from, if any, keyed by
+    /// their sequence number
+    deltas: BTreeMap<u64, (Option<NodeId>, C)>,
+    next_seq: u64,
+    /// The sequence number each peer has acknowledged receiving every delta
+    /// before
+    acked: HashMap<NodeId, u64>,
+    capacity: usize,
+}
+
+impl<C> DeltaReplicator<C>
+where
+    C: Crdt + Default + PartialEq,
+{
+    /// Creates a replicator syncing with `peers`, buffering at most `capacity`
+    /// deltas
+    pub fn new(peers: impl IntoIterator<Item = NodeId>, capacity: usize) -> Self {
+        Self {
+            state: C::default(),
+            deltas: BTreeMap::new(),
+            next_seq: 0,
+            acked: peers.into_iter().map(|peer| (peer, 0)).collect(),
+            capacity,
+        }
+    }
+
+    pub fn state(&self) -> &C {
+        &self.state
+    }
+
+    /// Applies a delta produced by a local mutation
+    pub fn apply(&mut self, delta: C) {
+        self.state.merge(&delta);
+        self.buffer(None, delta);
+    }
+
+    /// Returns what should be sent to `peer`, or `None` if it is up to date.
+    /// Deltas received from `peer` are not sent back to it.
+    pub fn sync_for(&self, peer: &NodeId) -> Option<DeltaSync<C>> {
+        let acked = self.acked.get(peer).copied().unwrap_or_default();
+
+        if acked >= self.next_seq {
+            return None;
+        }
+
+        match self.deltas.keys().next() {
+            Some(oldest) if *oldest <= acked => {
+                let mut group = C::default();
+                for (origin, delta) in self.deltas.range(acked..).map(|(_, delta)| delta) {
+                    if origin.as_ref() != Some(peer) {
+                        group.merge(delta);
+                    }
+                }
+
+                Some(DeltaSync {
+                    seq: self.next_seq,
+                    full: false,
+                    state: group,
+                })
+            }
+            // The peer has fallen behind the oldest buffered delta
+            _ => Some(DeltaSync {
+                seq: self.next_seq,
+                full: true,
+                state: self.state.clone(),
+            }),
+        }
+    }
+
+    /// Merges state received from `peer`, returning the sequence number to
+    /// acknowledge
+    pub fn receive(&mut self, peer: &NodeId, sync: DeltaSync<C>) -> u64 {
+        let previous = self.state.clone();
+        self.state.merge(&sync.state);
+
+        // Only forward state that taught us something, so deltas stop
+        // propagating once every replica has them
+        if self.state != previous {
+            self.buffer(Some(peer.clone()), sync.state);
+            self.skip_origin(peer);
+        }
+
+        sync.seq
+    }
+
+    /// Records that `peer` has received every delta before `seq`
+    pub fn ack(&mut self, peer: &NodeId, seq: u64) {
+        let acked = self.acked.entry(peer.clone()).or_default();
+        *acked = (*acked).max(seq);
+        self.skip_origin(peer);
+
+        let min_acked = self.acked.values().copied().min().unwrap_or(self.next_seq);
+        self.deltas = self.deltas.split_off(&min_acked);
+    }
+
+    /// Advances the acknowledgement of `peer` past deltas received from it,
+    /// which it does not need to be sent, so they can be dropped once every
+    /// other peer has them
+    fn skip_origin(&mut self, peer: &NodeId) {
+        let Some(acked) = self.acked.get_mut(peer) else {
+            return;
+        };
+
+        while let Some((Some(origin), _)) = self.deltas.get(acked) {
+            if origin != peer {
+                break;
+            }
+            *acked += 1;
+        }
+    }
+
+    fn buffer(&mut self, origin: Option<NodeId>, delta: C) {
+        self.deltas.insert(self.next_seq, (origin, delta));
+        self.next_seq += 1;
+
+        while self.deltas.len() > self.capacity {
+            self.deltas.pop_first();
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::Crdt;
+use crate::node::NodeId;
+
+/// A grow-only counter, holding each node's contribution to the total
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GCounter {
+    counts: BTreeMap<NodeId, u64>,
+}
+
+impl GCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `delta` to the contribution of `node`
+    pub fn increment(&mut self, node: &NodeId, delta: u64) {
+        *self.counts.entry(node.clone()).or_default() += delta;
+    }
+
+    /// Returns the contribution of `node` to the total
+    pub fn get(&self, node: &NodeId) -> u64 {
+        self.counts.get(node).copied().unwrap_or_default()
+    }
+}
+
+impl Crdt for GCounter {
+    type Value = u64;
+
+    fn merge(&mut self, other: &Self) {
+        for (node, count) in &other.counts {
+            let entry = self.counts.entry(node.clone()).or_default();
+            *entry = (*entry).max(*count);
+        }
+    }
+
+    fn value(&self) -> Self::Value {
+        self.counts.values().sum()
+    }
+}
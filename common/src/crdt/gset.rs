@@ -0,0 +1,53 @@
+use std::collections::BTreeSet;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use super::Crdt;
+
+/// A grow-only set
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GSet<T: Ord> {
+    elements: BTreeSet<T>,
+}
+
+impl<T: Ord> GSet<T> {
+    pub fn new() -> Self {
+        Self {
+            elements: BTreeSet::new(),
+        }
+    }
+
+    pub fn insert(&mut self, element: T) {
+        self.elements.insert(element);
+    }
+
+    pub fn contains(&self, element: &T) -> bool {
+        self.elements.contains(element)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.elements.iter()
+    }
+}
+
+impl<T: Ord> Default for GSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Crdt for GSet<T>
+where
+    T: Ord + Clone + Serialize + DeserializeOwned,
+{
+    type Value = BTreeSet<T>;
+
+    fn merge(&mut self, other: &Self) {
+        self.elements.extend(other.elements.iter().cloned());
+    }
+
+    fn value(&self) -> Self::Value {
+        self.elements.clone()
+    }
+}
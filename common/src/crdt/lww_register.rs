@@ -0,0 +1,58 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use super::Crdt;
+use crate::node::NodeId;
+
+/// A last-writer-wins register.
+///
+/// Writes are ordered by timestamp, with ties broken by the writing node and
+/// then by value so that every replica picks the same winner.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LwwRegister<T> {
+    entry: Option<(u64, NodeId, T)>,
+}
+
+impl<T: Ord> LwwRegister<T> {
+    pub fn new() -> Self {
+        Self { entry: None }
+    }
+
+    /// Writes `value` at `timestamp` on behalf of `node`, unless a later write
+    /// has already been observed
+    pub fn set(&mut self, node: &NodeId, timestamp: u64, value: T) {
+        let entry = (timestamp, node.clone(), value);
+
+        if self.entry.as_ref().is_none_or(|current| entry > *current) {
+            self.entry = Some(entry);
+        }
+    }
+
+    /// Returns the timestamp of the winning write, if any
+    pub fn timestamp(&self) -> Option<u64> {
+        self.entry.as_ref().map(|(timestamp, _, _)| *timestamp)
+    }
+}
+
+impl<T: Ord> Default for LwwRegister<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Crdt for LwwRegister<T>
+where
+    T: Ord + Clone + Serialize + DeserializeOwned,
+{
+    type Value = Option<T>;
+
+    fn merge(&mut self, other: &Self) {
+        if let Some((timestamp, node, value)) = &other.entry {
+            self.set(node, *timestamp, value.clone());
+        }
+    }
+
+    fn value(&self) -> Self::Value {
+        self.entry.as_ref().map(|(_, _, value)| value.clone())
+    }
+}
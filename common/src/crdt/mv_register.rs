@@ -0,0 +1,79 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use super::Crdt;
+use crate::node::NodeId;
+
+type VersionVector = BTreeMap<NodeId, u64>;
+
+/// Returns whether `a` has observed every write `b` has, and strictly more
+fn dominates(a: &VersionVector, b: &VersionVector) -> bool {
+    a != b
+        && b.iter()
+            .all(|(node, count)| a.get(node).is_some_and(|other| other >= count))
+}
+
+/// A multi-value register.
+///
+/// Concurrent writes are all retained, each tagged with the version vector it
+/// was written at, until a later write that has observed them replaces them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MvRegister<T: Ord> {
+    entries: BTreeSet<(VersionVector, T)>,
+}
+
+impl<T: Ord + Clone> MvRegister<T> {
+    pub fn new() -> Self {
+        Self {
+            entries: BTreeSet::new(),
+        }
+    }
+
+    /// Writes `value` on behalf of `node`, replacing every value observed by
+    /// this replica
+    pub fn set(&mut self, node: &NodeId, value: T) {
+        let mut version = VersionVector::new();
+
+        for (entry_version, _) in &self.entries {
+            for (node, count) in entry_version {
+                let current = version.entry(node.clone()).or_default();
+                *current = (*current).max(*count);
+            }
+        }
+        *version.entry(node.clone()).or_default() += 1;
+
+        self.entries = BTreeSet::from([(version, value)]);
+    }
+}
+
+impl<T: Ord + Clone> Default for MvRegister<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Crdt for MvRegister<T>
+where
+    T: Ord + Clone + Serialize + DeserializeOwned,
+{
+    type Value = BTreeSet<T>;
+
+    fn merge(&mut self, other: &Self) {
+        let all: BTreeSet<_> = self.entries.union(&other.entries).cloned().collect();
+
+        self.entries = all
+            .iter()
+            .filter(|(version, _)| !all.iter().any(|(other, _)| dominates(other, version)))
+            .cloned()
+            .collect();
+    }
+
+    fn value(&self) -> Self::Value {
+        self.entries
+            .iter()
+            .map(|(_, value)| value.clone())
+            .collect()
+    }
+}
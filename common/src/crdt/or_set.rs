@@ -0,0 +1,91 @@
+use std::collections::BTreeSet;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use super::{Crdt, GCounter};
+use crate::node::NodeId;
+
+/// Uniquely identifies a single insertion into an [`OrSet`]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Dot {
+    pub node: NodeId,
+    pub counter: u64,
+}
+
+/// An observed-remove set.
+///
+/// Each insertion is tagged with a unique [`Dot`], and removing an element
+/// only removes the insertions this replica has observed. Concurrent inserts
+/// and removes of the same element therefore resolve in favor of the insert.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrSet<T: Ord> {
+    clock: GCounter,
+    entries: BTreeSet<(T, Dot)>,
+    tombstones: BTreeSet<Dot>,
+}
+
+impl<T: Ord + Clone> OrSet<T> {
+    pub fn new() -> Self {
+        Self {
+            clock: GCounter::new(),
+            entries: BTreeSet::new(),
+            tombstones: BTreeSet::new(),
+        }
+    }
+
+    /// Inserts `element` on behalf of `node`
+    pub fn insert(&mut self, node: &NodeId, element: T) {
+        self.clock.increment(node, 1);
+
+        let dot = Dot {
+            node: node.clone(),
+            counter: self.clock.get(node),
+        };
+        self.entries.insert((element, dot));
+    }
+
+    /// Removes every insertion of `element` observed by this replica
+    pub fn remove(&mut self, element: &T) {
+        let observed = self
+            .entries
+            .iter()
+            .filter(|(entry, _)| entry == element)
+            .map(|(_, dot)| dot.clone());
+
+        self.tombstones.extend(observed);
+    }
+
+    pub fn contains(&self, element: &T) -> bool {
+        self.entries
+            .iter()
+            .any(|(entry, dot)| entry == element && !self.tombstones.contains(dot))
+    }
+}
+
+impl<T: Ord + Clone> Default for OrSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Crdt for OrSet<T>
+where
+    T: Ord + Clone + Serialize + DeserializeOwned,
+{
+    type Value = BTreeSet<T>;
+
+    fn merge(&mut self, other: &Self) {
+        self.clock.merge(&other.clock);
+        self.entries.extend(other.entries.iter().cloned());
+        self.tombstones.extend(other.tombstones.iter().cloned());
+    }
+
+    fn value(&self) -> Self::Value {
+        self.entries
+            .iter()
+            .filter(|(_, dot)| !self.tombstones.contains(dot))
+            .map(|(element, _)| element.clone())
+            .collect()
+    }
+}
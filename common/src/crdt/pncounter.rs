@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+
+use super::{Crdt, GCounter};
+use crate::node::NodeId;
+
+/// A counter supporting both increments and decrements, tracked as a pair of
+/// grow-only counters
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PnCounter {
+    increments: GCounter,
+    decrements: GCounter,
+}
+
+impl PnCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn increment(&mut self, node: &NodeId, delta: u64) {
+        self.increments.increment(node, delta);
+    }
+
+    pub fn decrement(&mut self, node: &NodeId, delta: u64) {
+        self.decrements.increment(node, delta);
+    }
+}
+
+impl Crdt for PnCounter {
+    type Value = i64;
+
+    fn merge(&mut self, other: &Self) {
+        self.increments.merge(&other.increments);
+        self.decrements.merge(&other.decrements);
+    }
+
+    fn value(&self) -> Self::Value {
+        self.increments.value() as i64 - self.decrements.value() as i64
+    }
+}
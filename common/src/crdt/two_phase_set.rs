@@ -0,0 +1,63 @@
+use std::collections::BTreeSet;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use super::{Crdt, GSet};
+
+/// A set supporting removal, where removed elements can never be re-added
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TwoPhaseSet<T: Ord> {
+    added: GSet<T>,
+    removed: GSet<T>,
+}
+
+impl<T: Ord + Clone> TwoPhaseSet<T> {
+    pub fn new() -> Self {
+        Self {
+            added: GSet::new(),
+            removed: GSet::new(),
+        }
+    }
+
+    pub fn insert(&mut self, element: T) {
+        self.added.insert(element);
+    }
+
+    /// Removes `element` if it has been observed by this replica
+    pub fn remove(&mut self, element: &T) {
+        if self.added.contains(element) {
+            self.removed.insert(element.clone());
+        }
+    }
+
+    pub fn contains(&self, element: &T) -> bool {
+        self.added.contains(element) && !self.removed.contains(element)
+    }
+}
+
+impl<T: Ord + Clone> Default for TwoPhaseSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Crdt for TwoPhaseSet<T>
+where
+    T: Ord + Clone + Serialize + DeserializeOwned,
+{
+    type Value = BTreeSet<T>;
+
+    fn merge(&mut self, other: &Self) {
+        self.added.merge(&other.added);
+        self.removed.merge(&other.removed);
+    }
+
+    fn value(&self) -> Self::Value {
+        self.added
+            .iter()
+            .filter(|element| !self.removed.contains(element))
+            .cloned()
+            .collect()
+    }
+}
@@ -1,3 +1,25 @@
+// Lets code generated by `payload-derive` name this crate from inside it too
+extern crate self as common;
+
+pub mod codec;
+pub mod crdt;
 pub mod message;
+pub mod middleware;
+#[cfg(feature = "network")]
+pub mod network;
 pub mod node;
+pub mod outbox;
+mod pipe;
+pub mod router;
 pub mod runtime;
+#[cfg(feature = "sim")]
+pub mod sim;
+pub mod stats;
+pub mod trace;
+pub mod transport;
+pub mod writer;
+
+#[doc(hidden)]
+pub mod __private {
+    pub use serde;
+}
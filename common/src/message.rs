@@ -1,6 +1,9 @@
 use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub use payload_derive::MaelstromPayload;
 
 pub type MessageId = u64;
 
@@ -19,9 +22,34 @@ pub struct MessageBody<P> {
     pub payload: P,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(tag = "type")]
-#[serde(rename_all = "snake_case")]
+impl Message<Value> {
+    /// The message type of a message whose payload is not decoded, as
+    /// [`Payload::type_name`] gives it for decoded payloads
+    pub fn type_name(&self) -> Option<&str> {
+        self.body.payload["type"].as_str()
+    }
+}
+
+/// A payload enum whose variants are the message types a node exchanges,
+/// usually implemented with `#[derive(MaelstromPayload)]`
+pub trait Payload {
+    /// Every message type, as sent in the `type` field
+    const TYPES: &'static [&'static str];
+
+    /// The message type of this payload
+    fn type_name(&self) -> &'static str;
+    /// The message type of the reply to this payload, if it is a request
+    fn reply_type(&self) -> Option<&'static str>;
+    /// The message type of the request this payload replies to, if it is a
+    /// reply
+    fn request_type(&self) -> Option<&'static str>;
+
+    fn is_reply(&self) -> bool {
+        self.request_type().is_some()
+    }
+}
+
+#[derive(Debug, Clone, MaelstromPayload)]
 pub enum MessagePayload {
     Init {
         node_id: String,
@@ -0,0 +1,373 @@
+//! Layers of middleware between a node and its input and output streams,
+//! which can inspect, modify, drop or delay any message before the node or
+//! the writer sees it.
+
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::io::{AsyncRead, AsyncWrite, BufReader};
+use tokio::sync::mpsc::{
+    unbounded_channel, UnboundedReceiver, UnboundedSender, WeakUnboundedSender,
+};
+
+use crate::codec::{self, Codec, DecodeError};
+use crate::message::{Message, MessageId};
+use crate::pipe;
+use crate::trace::{Direction, Tracer};
+
+mod dedup;
+
+pub use dedup::Dedup;
+
+/// A message passing through the layers.
+///
+/// Only its addresses and IDs are decoded up front. Its payload is decoded
+/// once a layer looks at it, and the message is only encoded again if a
+/// layer modifies it, so layers that only route or filter messages cost
+/// little more than reading them.
+#[derive(Debug, Clone)]
+pub struct Envelope {
+    /// The frame the message was read or written as, until it is modified
+    frame: Option<Vec<u8>>,
+    header: Header,
+    message: Option<Message<Value>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Header {
+    src: String,
+    dest: String,
+    body: HeaderBody,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct HeaderBody {
+    msg_id: Option<MessageId>,
+    in_reply_to: Option<MessageId>,
+}
+
+impl Envelope {
+    /// Wraps `frame`, a frame of any codec, decoding only its header
+    fn decode(frame: Vec<u8>) -> Result<Self, DecodeError> {
+        Ok(Self {
+            header: codec::decode(&frame)?,
+            frame: Some(frame),
+            message: None,
+        })
+    }
+
+    pub fn src(&self) -> &str {
+        match &self.message {
+            Some(message) => &message.src,
+            None => &self.header.src,
+        }
+    }
+
+    pub fn dest(&self) -> &str {
+        match &self.message {
+            Some(message) => &message.dest,
+            None => &self.header.dest,
+        }
+    }
+
+    pub fn msg_id(&self) -> Option<MessageId> {
+        match &self.message {
+            Some(message) => message.body.msg_id,
+            None => self.header.body.msg_id,
+        }
+    }
+
+    pub fn in_reply_to(&self) -> Option<MessageId> {
+        match &self.message {
+            Some(message) => message.body.in_reply_to,
+            None => self.header.body.in_reply_to,
+        }
+    }
+
+    /// The frame the message was read or written as, unless a layer modified
+    /// it, for layers that need more of it than its decoded message keeps
+    pub fn frame(&self) -> Option<&[u8]> {
+        self.frame.as_deref()
+    }
+
+    /// The whole message, decoding its payload on first use
+    pub fn message(&mut self) -> &Message<Value> {
+        self.decoded()
+    }
+
+    /// The whole message, to be encoded again when it leaves the layers
+    pub fn message_mut(&mut self) -> &mut Message<Value> {
+        self.decoded();
+        self.frame = None;
+        self.message.as_mut().expect("message was decoded")
+    }
+
+    pub fn into_message(mut self) -> Message<Value> {
+        self.decoded();
+        self.message.expect("message was decoded")
+    }
+
+    fn decoded(&mut self) -> &mut Message<Value> {
+        let frame = &self.frame;
+        self.message.get_or_insert_with(|| {
+            let frame = frame
+                .as_deref()
+                .expect("unmodified messages keep their frame");
+            // Any frame whose header decodes is a valid document
+            codec::decode(frame).expect("frame decoded as a header")
+        })
+    }
+
+    /// The frame to hand on, encoding the message with `codec` if it was
+    /// modified or created by a layer
+    fn into_frame(self, codec: Codec) -> Vec<u8> {
+        if let Some(frame) = self.frame {
+            return frame;
+        }
+
+        let mut frame = Vec::new();
+        codec.encode(
+            &self.message.expect("modified messages are decoded"),
+            &mut frame,
+        );
+        frame
+    }
+}
+
+impl From<Message<Value>> for Envelope {
+    fn from(message: Message<Value>) -> Self {
+        Self {
+            frame: None,
+            header: Header {
+                src: message.src.clone(),
+                dest: message.dest.clone(),
+                body: HeaderBody {
+                    msg_id: message.body.msg_id,
+                    in_reply_to: message.body.in_reply_to,
+                },
+            },
+            message: Some(message),
+        }
+    }
+}
+
+/// What a layer does with a message it was handed
+#[derive(Debug)]
+pub enum Action {
+    /// Hands the message, possibly modified, on to the next layer
+    Pass(Envelope),
+    /// Hands the message on to the next layer once the duration has passed.
+    /// Messages after it are not held up.
+    Delay(Envelope, Duration),
+    /// Drops the message and answers it with the given reply in place of its
+    /// destination. The reply passes back through the layers the message
+    /// already passed through.
+    Reply(Envelope),
+    Drop,
+}
+
+/// A middleware layer, added to a runtime with
+/// [`Runtime::layer`](crate::runtime::Runtime::layer).
+///
+/// Layers are shared by the tasks reading and writing messages, so any state
+/// they keep must be synchronized.
+pub trait Layer: Send + Sync + 'static {
+    /// Called with every message read, before the node handles it
+    fn inbound(&self, envelope: Envelope) -> Action {
+        Action::Pass(envelope)
+    }
+
+    /// Called with every message the node sends, before it is written
+    fn outbound(&self, envelope: Envelope) -> Action {
+        Action::Pass(envelope)
+    }
+}
+
+/// The layers of a runtime, outermost first
+#[derive(Clone, Default)]
+pub(crate) struct Stack {
+    layers: Vec<Arc<dyn Layer>>,
+}
+
+impl fmt::Debug for Stack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Stack")
+            .field("layers", &self.layers.len())
+            .finish()
+    }
+}
+
+/// The frames that made it inward through the layers, or the error that
+/// ended the input stream
+pub(crate) type Inbound = UnboundedReceiver<io::Result<Vec<u8>>>;
+
+/// Hands the frames a node writes outward through the layers
+pub(crate) struct Outbound {
+    pipeline: Pipeline,
+    /// Keeps the output stream open until the node stops writing
+    _open: UnboundedSender<Vec<u8>>,
+}
+
+impl Outbound {
+    pub(crate) fn send(&self, frame: Vec<u8>) {
+        let codec = Codec::of(&frame);
+        let envelope = Envelope::decode(frame).expect("failed decoding an encoded message");
+        self.pipeline
+            .outbound(self.pipeline.layers.len(), envelope, codec);
+    }
+}
+
+impl Stack {
+    pub(crate) fn push(&mut self, layer: impl Layer) {
+        self.layers.push(Arc::new(layer));
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// Interposes the layers between the node and `reader` and `writer`,
+    /// returning the frames the node should handle and the handle it should
+    /// write frames to instead. Frames are traced to `tracer` as they are
+    /// read from `reader` and written to `writer`, outside of the layers.
+    ///
+    /// Inbound messages pass through the layers outermost first, and
+    /// outbound messages innermost first. Messages keep the codec they were
+    /// read or written in.
+    pub(crate) fn wrap<R, W>(
+        &self,
+        reader: R,
+        writer: W,
+        tracer: Option<Tracer>,
+    ) -> (Inbound, Outbound)
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+        W: AsyncWrite + Send + Unpin + 'static,
+    {
+        let (inbound, node_frames) = unbounded_channel();
+        let outbound = pipe::spawn_writer(writer, tracer.clone());
+        let pipeline = Pipeline {
+            layers: self.layers.iter().cloned().collect(),
+            inbound: inbound.downgrade(),
+            outbound: outbound.downgrade(),
+        };
+
+        // The inbound direction is closed once the input stream ends and no
+        // message is delayed in it, even if replies could still be sent to it
+        tokio::spawn(read_frames(reader, tracer, inbound, pipeline.clone()));
+
+        (
+            node_frames,
+            Outbound {
+                pipeline,
+                _open: outbound,
+            },
+        )
+    }
+}
+
+/// Where messages go once they have made it through the layers
+#[derive(Clone)]
+struct Pipeline {
+    /// Layers outermost first
+    layers: Arc<[Arc<dyn Layer>]>,
+    /// Frames to hand to the node
+    inbound: WeakUnboundedSender<io::Result<Vec<u8>>>,
+    /// Frames to write to the output stream
+    outbound: WeakUnboundedSender<Vec<u8>>,
+}
+
+impl Pipeline {
+    /// Passes `envelope` inward through the layers from the one at `from`,
+    /// and on to the node
+    fn inbound(&self, from: usize, mut envelope: Envelope, codec: Codec) {
+        for index in from..self.layers.len() {
+            match self.layers[index].inbound(envelope) {
+                Action::Pass(passed) => envelope = passed,
+                Action::Delay(delayed, delay) => {
+                    let (pipeline, open) = (self.clone(), self.inbound.upgrade());
+                    tokio::spawn(async move {
+                        tokio::time::sleep(delay).await;
+                        pipeline.inbound(index + 1, delayed, codec);
+                        drop(open);
+                    });
+                    return;
+                }
+                Action::Reply(reply) => return self.outbound(index, reply, codec),
+                Action::Drop => return,
+            }
+        }
+
+        if let Some(tx) = self.inbound.upgrade() {
+            let _ = tx.send(Ok(envelope.into_frame(codec)));
+        }
+    }
+
+    /// Passes `envelope` outward through the layers outside of the one at
+    /// `until`, and on to the output stream
+    fn outbound(&self, until: usize, mut envelope: Envelope, codec: Codec) {
+        for index in (0..until).rev() {
+            match self.layers[index].outbound(envelope) {
+                Action::Pass(passed) => envelope = passed,
+                Action::Delay(delayed, delay) => {
+                    let (pipeline, open) = (self.clone(), self.outbound.upgrade());
+                    tokio::spawn(async move {
+                        tokio::time::sleep(delay).await;
+                        pipeline.outbound(index, delayed, codec);
+                        drop(open);
+                    });
+                    return;
+                }
+                Action::Reply(reply) => return self.inbound(index + 1, reply, codec),
+                Action::Drop => return,
+            }
+        }
+
+        if let Some(tx) = self.outbound.upgrade() {
+            let _ = tx.send(envelope.into_frame(codec));
+        }
+    }
+}
+
+/// Hands every frame read from `reader` inward through `pipeline`, until the
+/// stream ends or a frame cannot be read or decoded, which is handed to the
+/// node in its place
+async fn read_frames<R>(
+    reader: R,
+    tracer: Option<Tracer>,
+    inbound: UnboundedSender<io::Result<Vec<u8>>>,
+    pipeline: Pipeline,
+) where
+    R: AsyncRead + Unpin,
+{
+    let mut reader = BufReader::new(reader);
+
+    loop {
+        let mut frame = Vec::new();
+        match codec::read_frame(&mut reader, &mut frame).await {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(error) => {
+                let _ = inbound.send(Err(error));
+                return;
+            }
+        }
+
+        if let Some(tracer) = &tracer {
+            tracer.record(Direction::Inbound, &codec::to_json(&frame));
+        }
+
+        let codec = Codec::of(&frame);
+        match Envelope::decode(frame) {
+            Ok(envelope) => pipeline.inbound(0, envelope, codec),
+            Err(error) => {
+                let _ = inbound.send(Err(io::Error::new(io::ErrorKind::InvalidData, error)));
+                return;
+            }
+        }
+    }
+}
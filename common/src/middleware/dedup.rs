@@ -0,0 +1,76 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use super::{Action, Envelope, Layer};
+use crate::message::MessageId;
+use crate::node::NodeId;
+
+/// Hands each message to the node at most once, identifying messages by
+/// their sender and ID.
+///
+/// Repeats of a request the node has replied to are answered with the same
+/// reply again, and repeats of anything else are dropped. Only the last
+/// `window` messages are remembered. Payloads are never decoded, so replies
+/// are repeated exactly as they were written.
+#[derive(Debug)]
+pub struct Dedup {
+    window: usize,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// The reply to each remembered message, once there is one
+    replies: HashMap<(NodeId, MessageId), Option<Envelope>>,
+    /// Remembered messages, oldest first
+    order: VecDeque<(NodeId, MessageId)>,
+}
+
+impl Dedup {
+    pub fn new(window: usize) -> Self {
+        assert!(window > 0, "dedup window must be positive");
+
+        Self {
+            window,
+            state: Mutex::default(),
+        }
+    }
+}
+
+impl Layer for Dedup {
+    fn inbound(&self, envelope: Envelope) -> Action {
+        let Some(msg_id) = envelope.msg_id() else {
+            return Action::Pass(envelope);
+        };
+        let key = (envelope.src().to_string(), msg_id);
+        let mut state = self.state.lock().expect("poisoned lock");
+
+        match state.replies.get(&key) {
+            Some(Some(reply)) => Action::Reply(reply.clone()),
+            Some(None) => Action::Drop,
+            None => {
+                state.replies.insert(key.clone(), None);
+                state.order.push_back(key);
+                if state.order.len() > self.window {
+                    let oldest = state.order.pop_front().expect("window is not empty");
+                    state.replies.remove(&oldest);
+                }
+
+                Action::Pass(envelope)
+            }
+        }
+    }
+
+    fn outbound(&self, envelope: Envelope) -> Action {
+        if let Some(in_reply_to) = envelope.in_reply_to() {
+            let key = (envelope.dest().to_string(), in_reply_to);
+            let mut state = self.state.lock().expect("poisoned lock");
+
+            if let Some(reply) = state.replies.get_mut(&key) {
+                reply.get_or_insert_with(|| envelope.clone());
+            }
+        }
+
+        Action::Pass(envelope)
+    }
+}
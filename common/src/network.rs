@@ -0,0 +1,342 @@
+//! An in-memory network connecting nodes, whether they run in a simulated
+//! cluster or in processes of their own, with support for injecting faults.
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::Value;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use crate::codec::Codec;
+use crate::message::Message;
+use crate::node::NodeId;
+
+/// Probabilistic faults applied to every message routed between nodes
+#[derive(Debug, Clone, Default)]
+pub struct Faults {
+    /// Probability that a message is dropped
+    pub drop: f64,
+    /// Probability that a message is delivered twice
+    pub duplicate: f64,
+    /// Probability that a message is delayed by up to `max_delay`
+    pub delay: f64,
+    pub max_delay: Duration,
+    /// Probability that a message is held back until after the next message
+    /// to the same destination, or for up to `max_delay` if none arrives
+    pub reorder: f64,
+}
+
+impl Faults {
+    /// Panics unless every probability is between 0 and 1
+    fn validate(&self) {
+        let probabilities = [
+            ("drop", self.drop),
+            ("duplicate", self.duplicate),
+            ("delay", self.delay),
+            ("reorder", self.reorder),
+        ];
+
+        for (name, probability) in probabilities {
+            assert!(
+                (0.0..=1.0).contains(&probability),
+                "{name} probability must be between 0 and 1, not {probability}"
+            );
+        }
+    }
+}
+
+/// A set of links between nodes that cannot carry messages.
+///
+/// Links to and from clients are never cut.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Partition {
+    cut: HashSet<(NodeId, NodeId)>,
+}
+
+impl Partition {
+    /// Cuts every link between nodes in different `components`
+    pub fn components(components: &[Vec<NodeId>]) -> Self {
+        let mut partition = Self::default();
+
+        for (i, a) in components.iter().enumerate() {
+            for b in &components[i + 1..] {
+                partition.cut_between(a, b);
+            }
+        }
+
+        partition
+    }
+
+    /// Splits `nodes` into a majority made up of the first `n / 2 + 1` nodes
+    /// and a minority made up of the rest
+    pub fn majority_minority(nodes: &[NodeId]) -> Self {
+        let (majority, minority) = nodes.split_at(nodes.len() / 2 + 1);
+        Self::components(&[majority.to_vec(), minority.to_vec()])
+    }
+
+    /// Cuts `node` off from every other node in `nodes`
+    pub fn isolate(node: &NodeId, nodes: &[NodeId]) -> Self {
+        let rest: Vec<_> = nodes.iter().filter(|n| *n != node).cloned().collect();
+        Self::components(&[vec![node.clone()], rest])
+    }
+
+    /// Splits `nodes` into two halves that can only communicate through the
+    /// node in the middle, which can reach both
+    ///
+    /// # Panics
+    ///
+    /// If `nodes` is empty, as there is no node to bridge the halves
+    pub fn bridge(nodes: &[NodeId]) -> Self {
+        assert!(!nodes.is_empty(), "a bridge needs at least one node");
+        let middle = nodes.len() / 2;
+        let mut partition = Self::default();
+        partition.cut_between(&nodes[..middle], &nodes[middle + 1..]);
+        partition
+    }
+
+    /// Returns whether messages from `src` to `dest` are dropped
+    pub fn is_cut(&self, src: &NodeId, dest: &NodeId) -> bool {
+        self.cut.contains(&(src.clone(), dest.clone()))
+    }
+
+    fn cut_between(&mut self, a: &[NodeId], b: &[NodeId]) {
+        for x in a {
+            for y in b {
+                self.cut.insert((x.clone(), y.clone()));
+                self.cut.insert((y.clone(), x.clone()));
+            }
+        }
+    }
+}
+
+/// A handle to a network routing messages between the nodes that joined it,
+/// and delivering every other message to its clients.
+///
+/// Nodes can run anywhere, such as in-process or in other processes, as long
+/// as each has an inbox that takes JSON frames.
+#[derive(Debug, Clone)]
+pub struct Network {
+    events: UnboundedSender<Event>,
+}
+
+impl Network {
+    /// Starts a network with no nodes, returning it and the receiver for
+    /// messages to its clients
+    pub fn start() -> (Self, UnboundedReceiver<Message<Value>>) {
+        Self::start_seeded(rand::random())
+    }
+
+    /// Like [`Network::start`], but draws faults from an RNG seeded with
+    /// `seed`
+    pub fn start_seeded(seed: u64) -> (Self, UnboundedReceiver<Message<Value>>) {
+        let (events, events_rx) = unbounded_channel();
+        let (client_tx, client_rx) = unbounded_channel();
+
+        tokio::spawn(Router::new(events.clone(), client_tx, seed).run(events_rx));
+
+        (Self { events }, client_rx)
+    }
+
+    /// Adds the node `node_id` to the network, delivering its messages to
+    /// `inbox` as JSON frames. Messages to a node whose inbox has closed are
+    /// dropped.
+    pub fn join(&self, node_id: NodeId, inbox: UnboundedSender<Vec<u8>>) {
+        self.send_event(Event::Join { node_id, inbox });
+    }
+
+    /// Routes `message`, sent by a node or a client
+    pub fn send(&self, message: Message<Value>) {
+        self.send_event(Event::Send(message));
+    }
+
+    /// Applies `faults` to every message subsequently sent between nodes
+    ///
+    /// # Panics
+    ///
+    /// If any probability in `faults` is not between 0 and 1
+    pub fn set_faults(&self, faults: Faults) {
+        faults.validate();
+        self.send_event(Event::SetFaults(faults));
+    }
+
+    /// Replaces the current partition, if any, with `partition`
+    pub fn partition(&self, partition: Partition) {
+        self.send_event(Event::Partition(partition));
+    }
+
+    fn send_event(&self, event: Event) {
+        // The router keeps a sender of its own, so it never stops
+        self.events.send(event).expect("router stopped");
+    }
+}
+
+/// Events processed by the router, in the order they are sent
+#[derive(Debug)]
+enum Event {
+    /// A message sent by a node or the client
+    Send(Message<Value>),
+    /// A previously delayed or held back message that is now due
+    Release(Message<Value>),
+    /// Releases the message held back for `dest`, if it is still the one
+    /// identified by `seq`
+    Flush {
+        dest: NodeId,
+        seq: u64,
+    },
+    SetFaults(Faults),
+    Partition(Partition),
+    /// Adds a node to the cluster, delivering its messages to `inbox`
+    Join {
+        node_id: NodeId,
+        inbox: UnboundedSender<Vec<u8>>,
+    },
+}
+
+/// Delivers messages to the inbox of their destination node, or to the
+/// client if they are not addressed to a node in the network
+struct Router {
+    events: UnboundedSender<Event>,
+    inboxes: HashMap<NodeId, UnboundedSender<Vec<u8>>>,
+    client_tx: UnboundedSender<Message<Value>>,
+    faults: Faults,
+    partition: Partition,
+    /// Messages held back to be reordered, keyed by destination
+    held: HashMap<NodeId, (u64, Message<Value>)>,
+    curr_seq: u64,
+    rng: StdRng,
+}
+
+impl Router {
+    fn new(
+        events: UnboundedSender<Event>,
+        client_tx: UnboundedSender<Message<Value>>,
+        seed: u64,
+    ) -> Self {
+        Self {
+            events,
+            inboxes: HashMap::new(),
+            client_tx,
+            faults: Faults::default(),
+            partition: Partition::default(),
+            held: HashMap::new(),
+            curr_seq: Default::default(),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    async fn run(mut self, mut events_rx: UnboundedReceiver<Event>) {
+        while let Some(event) = events_rx.recv().await {
+            match event {
+                Event::Send(message) => self.route(message),
+                Event::Release(message) => self.deliver(message),
+                Event::Flush { dest, seq } => {
+                    if matches!(self.held.get(&dest), Some((held, _)) if *held == seq) {
+                        let (_, message) = self.held.remove(&dest).expect("held message");
+                        self.deliver(message);
+                    }
+                }
+                Event::SetFaults(faults) => self.faults = faults,
+                Event::Partition(partition) => self.partition = partition,
+                Event::Join { node_id, inbox } => {
+                    self.inboxes.insert(node_id, inbox);
+                }
+            }
+        }
+    }
+
+    fn is_between_nodes(&self, message: &Message<Value>) -> bool {
+        self.inboxes.contains_key(&message.src) && self.inboxes.contains_key(&message.dest)
+    }
+
+    /// Applies faults to a newly sent message
+    fn route(&mut self, message: Message<Value>) {
+        if !self.is_between_nodes(&message) {
+            self.deliver(message);
+            return;
+        }
+
+        if self.rng.gen_bool(self.faults.drop) {
+            return;
+        }
+
+        let copies = if self.rng.gen_bool(self.faults.duplicate) {
+            2
+        } else {
+            1
+        };
+
+        for _ in 0..copies {
+            let message = message.clone();
+
+            if self.rng.gen_bool(self.faults.delay) {
+                let delay = self.random_delay();
+                self.release_after(delay, Event::Release(message));
+            } else if self.rng.gen_bool(self.faults.reorder) {
+                self.hold(message);
+            } else {
+                self.deliver(message);
+            }
+        }
+    }
+
+    fn random_delay(&mut self) -> Duration {
+        self.rng.gen_range(Duration::ZERO..=self.faults.max_delay)
+    }
+
+    fn release_after(&self, delay: Duration, event: Event) {
+        let events = self.events.clone();
+
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            let _ = events.send(event);
+        });
+    }
+
+    /// Holds `message` back until after the next message to its destination
+    fn hold(&mut self, message: Message<Value>) {
+        self.curr_seq += 1;
+        let dest = message.dest.clone();
+
+        if let Some((_, previous)) = self.held.remove(&dest) {
+            self.deliver(previous);
+        }
+        self.held.insert(dest.clone(), (self.curr_seq, message));
+
+        let delay = self.random_delay();
+        self.release_after(
+            delay,
+            Event::Flush {
+                dest,
+                seq: self.curr_seq,
+            },
+        );
+    }
+
+    /// Delivers `message` unless its link is cut, followed by any message held
+    /// back for the same destination
+    fn deliver(&mut self, message: Message<Value>) {
+        if self.partition.is_cut(&message.src, &message.dest) {
+            return;
+        }
+
+        let held = self.held.remove(&message.dest);
+
+        // A node that has stopped simply drops its messages
+        match self.inboxes.get(&message.dest) {
+            Some(inbox) => {
+                let mut frame = Vec::new();
+                Codec::Json.encode(&message, &mut frame);
+                let _ = inbox.send(frame);
+            }
+            None => {
+                let _ = self.client_tx.send(message);
+            }
+        }
+
+        if let Some((_, held)) = held {
+            self.deliver(held);
+        }
+    }
+}
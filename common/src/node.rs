@@ -1,18 +1,69 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use tokio::sync::mpsc::UnboundedSender;
 
 use crate::message::{Message, MessageId};
+use crate::outbox::Outbox;
+use crate::router::Router;
 
 pub type NodeId = String;
 
 pub trait Node<'de> {
-    type Payload: Serialize + Deserialize<'de> + Send;
+    /// The payload of messages the node receives. It may borrow from the line
+    /// the message was read from, which outlives the call to `handle_message`.
+    type Payload: Deserialize<'de> + Send;
+    /// The payload of messages the node sends
+    type Output: Serialize + Send + 'static;
 
     fn handle_message(&mut self, message: Message<Self::Payload>);
-    fn from_init(
-        node_id: NodeId,
-        neighbors: Vec<NodeId>,
-        tx: UnboundedSender<Message<Self::Payload>>,
-    ) -> Self;
+    fn from_init(node_id: NodeId, neighbors: Vec<NodeId>, tx: Outbox<Self::Output>) -> Self;
+    fn next_msg_id(&mut self) -> MessageId;
+}
+
+/// A node whose messages are handed to handlers registered by message type,
+/// see [`Runtime::start_routed`](crate::runtime::Runtime::start_routed).
+///
+/// Requests of types without a handler are answered with a
+/// [`NOT_SUPPORTED`](crate::router::NOT_SUPPORTED) error, while replies and
+/// errors without one are dropped.
+pub trait RoutedNode: Sized {
+    /// The payload of messages the node sends
+    type Output: Serialize + Send + 'static;
+
+    fn from_init(node_id: NodeId, neighbors: Vec<NodeId>, tx: Outbox<Self::Output>) -> Self;
     fn next_msg_id(&mut self) -> MessageId;
+
+    /// Registers handlers for the messages the node receives, by type
+    fn route(router: &mut Router<Self>);
+}
+
+/// A node whose messages can be handled concurrently by a pool of workers, see
+/// [`Runtime::start_concurrent`](crate::runtime::Runtime::start_concurrent).
+///
+/// Unlike [`Node`], handlers only get a shared reference to the node, so any
+/// state they share must be synchronized.
+pub trait ConcurrentNode: Send + Sync {
+    type Payload: DeserializeOwned + Send + 'static;
+    type Output: Serialize + Send + 'static;
+
+    fn handle_message(&self, message: Message<Self::Payload>);
+    fn from_init(node_id: NodeId, neighbors: Vec<NodeId>, tx: Outbox<Self::Output>) -> Self;
+    fn next_msg_id(&self) -> MessageId;
+
+    /// Messages with the same key are handled in order by the same worker,
+    /// while messages without one may be handled by any worker. Keys can be
+    /// derived from any hashable value with [`dispatch_key`].
+    fn dispatch_key(&self, _message: &Message<Self::Payload>) -> Option<u64> {
+        None
+    }
+}
+
+/// Derives a dispatch key from `value`, such as a message's `src` or the key
+/// it reads
+pub fn dispatch_key<K: Hash + ?Sized>(value: &K) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
 }
@@ -0,0 +1,341 @@
+//! A bounded queue of messages a node has sent but the runtime has not yet
+//! written, with an explicit policy for when it fills up.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, Weak};
+
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tokio::sync::Notify;
+
+use crate::message::Message;
+
+/// What sending to a full outbox does
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Blocks the sender until the writer makes room.
+    ///
+    /// Blocking requires the multi-threaded runtime. On a current-thread
+    /// runtime the writer could never run, so a full outbox is reported as
+    /// [`SendError::Full`] instead.
+    #[default]
+    Block,
+    /// Drops the oldest queued message to make room
+    DropOldest,
+    /// Returns [`SendError::Full`] to the sender
+    Reject,
+}
+
+impl FromStr for Overflow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "block" => Ok(Self::Block),
+            "drop-oldest" => Ok(Self::DropOldest),
+            "reject" => Ok(Self::Reject),
+            other => Err(format!("unknown overflow policy {other}")),
+        }
+    }
+}
+
+pub enum SendError<P> {
+    /// The outbox was full and its policy rejected the message
+    Full(Message<P>),
+    /// The runtime has stopped writing messages
+    Closed(Message<P>),
+}
+
+impl<P> SendError<P> {
+    pub fn into_message(self) -> Message<P> {
+        match self {
+            Self::Full(message) | Self::Closed(message) => message,
+        }
+    }
+}
+
+impl<P> fmt::Debug for SendError<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full(_) => f.write_str("Full(..)"),
+            Self::Closed(_) => f.write_str("Closed(..)"),
+        }
+    }
+}
+
+impl<P> fmt::Display for SendError<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full(_) => f.write_str("outbox is full"),
+            Self::Closed(_) => f.write_str("outbox is closed"),
+        }
+    }
+}
+
+impl<P> std::error::Error for SendError<P> {}
+
+/// A snapshot of an outbox's queue
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// Messages currently queued
+    pub depth: usize,
+    /// The most messages ever queued at once
+    pub max_depth: usize,
+    /// Messages dropped to make room for newer ones
+    pub dropped: u64,
+    /// Messages rejected because the outbox was full
+    pub rejected: u64,
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} queued (at most {}), {} dropped, {} rejected",
+            self.depth, self.max_depth, self.dropped, self.rejected
+        )
+    }
+}
+
+struct State<P> {
+    messages: VecDeque<Message<P>>,
+    senders: usize,
+    /// Whether the receiver has been dropped
+    closed: bool,
+    metrics: Metrics,
+}
+
+struct Shared<P> {
+    state: Mutex<State<P>>,
+    capacity: usize,
+    overflow: Overflow,
+    /// Signalled when a message is queued or the last sender is dropped
+    not_empty: Notify,
+    /// Signalled when a message is dequeued or the receiver is dropped
+    not_full: Condvar,
+}
+
+/// The sending half of an outbox, handed to a node to send messages with
+pub struct Outbox<P> {
+    shared: Arc<Shared<P>>,
+}
+
+/// The receiving half of an outbox, drained by the runtime's writer
+pub struct Receiver<P> {
+    shared: Arc<Shared<P>>,
+}
+
+/// Reads the metrics of an outbox without keeping it open
+pub struct MetricsHandle<P> {
+    shared: Weak<Shared<P>>,
+}
+
+/// Creates an outbox holding at most `capacity` messages
+pub fn channel<P>(capacity: usize, overflow: Overflow) -> (Outbox<P>, Receiver<P>) {
+    assert!(capacity > 0, "outbox capacity must be positive");
+
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            messages: VecDeque::with_capacity(capacity),
+            senders: 1,
+            closed: false,
+            metrics: Metrics::default(),
+        }),
+        capacity,
+        overflow,
+        not_empty: Notify::new(),
+        not_full: Condvar::new(),
+    });
+
+    (
+        Outbox {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+impl<P> Outbox<P> {
+    /// Queues `message` to be written, applying the outbox's overflow policy
+    /// if it is full
+    pub fn send(&self, message: Message<P>) -> Result<(), SendError<P>> {
+        let mut state = self.shared.lock();
+
+        if state.messages.len() >= self.shared.capacity && !state.closed {
+            match self.shared.overflow {
+                Overflow::Block => match self.wait_for_room(state) {
+                    Some(room) => state = room,
+                    None => return Err(self.reject(message)),
+                },
+                Overflow::DropOldest => {
+                    state.messages.pop_front();
+                    state.metrics.dropped += 1;
+                }
+                Overflow::Reject => {
+                    drop(state);
+                    return Err(self.reject(message));
+                }
+            }
+        }
+
+        if state.closed {
+            return Err(SendError::Closed(message));
+        }
+
+        state.messages.push_back(message);
+        state.metrics.depth = state.messages.len();
+        state.metrics.max_depth = state.metrics.max_depth.max(state.metrics.depth);
+        drop(state);
+
+        self.shared.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Queues `message` like [`Outbox::send`], but treats a full outbox like
+    /// a lossy network, dropping the message. Every drop is logged on stderr
+    /// and counted as rejected. Senders that expect replies must already
+    /// cope with lost messages, and senders that must act on a drop should
+    /// use [`Outbox::send`] instead.
+    ///
+    /// # Panics
+    ///
+    /// If the runtime has stopped writing messages
+    pub fn send_or_drop(&self, message: Message<P>) {
+        match self.send(message) {
+            Ok(()) => {}
+            Err(SendError::Full(message)) => {
+                eprintln!("outbox: full, dropped a message to {}", message.dest);
+            }
+            Err(SendError::Closed(_)) => panic!("failed sending message"),
+        }
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        self.shared.lock().metrics
+    }
+
+    fn reject(&self, message: Message<P>) -> SendError<P> {
+        self.shared.lock().metrics.rejected += 1;
+        SendError::Full(message)
+    }
+
+    /// Blocks until the outbox has room or is closed, or returns `None` if
+    /// blocking would deadlock the runtime
+    fn wait_for_room<'a>(
+        &'a self,
+        state: MutexGuard<'a, State<P>>,
+    ) -> Option<MutexGuard<'a, State<P>>> {
+        let wait = || {
+            self.shared
+                .not_full
+                .wait_while(state, |state| {
+                    state.messages.len() >= self.shared.capacity && !state.closed
+                })
+                .expect("poisoned lock")
+        };
+
+        match Handle::try_current().map(|handle| handle.runtime_flavor()) {
+            Ok(RuntimeFlavor::MultiThread) => Some(tokio::task::block_in_place(wait)),
+            Ok(_) => None,
+            Err(_) => Some(wait()),
+        }
+    }
+}
+
+impl<P> fmt::Debug for Outbox<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Outbox")
+            .field("capacity", &self.shared.capacity)
+            .field("overflow", &self.shared.overflow)
+            .field("metrics", &self.metrics())
+            .finish()
+    }
+}
+
+impl<P> Clone for Outbox<P> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<P> Drop for Outbox<P> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+
+        if state.senders == 0 {
+            drop(state);
+            self.shared.not_empty.notify_one();
+        }
+    }
+}
+
+impl<P> Receiver<P> {
+    /// Waits for the next queued message, or returns `None` once every
+    /// sender has been dropped and the queue is empty
+    pub async fn recv(&mut self) -> Option<Message<P>> {
+        loop {
+            if let Some(message) = self.try_recv() {
+                return Some(message);
+            }
+
+            if self.shared.lock().senders == 0 {
+                // A message may have been queued before the last sender was
+                // dropped
+                return self.try_recv();
+            }
+
+            self.shared.not_empty.notified().await;
+        }
+    }
+
+    /// Takes the next queued message without waiting
+    pub fn try_recv(&mut self) -> Option<Message<P>> {
+        let mut state = self.shared.lock();
+        let message = state.messages.pop_front()?;
+        state.metrics.depth = state.messages.len();
+        drop(state);
+
+        self.shared.not_full.notify_one();
+        Some(message)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shared.lock().messages.is_empty()
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        self.shared.lock().metrics
+    }
+
+    pub fn metrics_handle(&self) -> MetricsHandle<P> {
+        MetricsHandle {
+            shared: Arc::downgrade(&self.shared),
+        }
+    }
+}
+
+impl<P> MetricsHandle<P> {
+    /// Returns the outbox's metrics, or `None` once it has been dropped
+    pub fn get(&self) -> Option<Metrics> {
+        Some(self.shared.upgrade()?.lock().metrics)
+    }
+}
+
+impl<P> Drop for Receiver<P> {
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.not_full.notify_all();
+    }
+}
+
+impl<P> Shared<P> {
+    fn lock(&self) -> MutexGuard<'_, State<P>> {
+        self.state.lock().expect("poisoned lock")
+    }
+}
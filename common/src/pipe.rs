@@ -0,0 +1,52 @@
+//! In-memory pipes standing in for a node's stdin and stdout, wherever a
+//! node's runtime is driven by something other than a process's streams.
+
+use tokio::io::{AsyncWrite, AsyncWriteExt, DuplexStream};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+use crate::codec;
+use crate::trace::{Direction, Tracer};
+
+/// How many bytes a pipe buffers before its writer waits for its reader
+const CAPACITY: usize = 64 * 1024;
+
+/// Creates a pipe, returning its two ends. Bytes written to either end are
+/// read from the other.
+pub(crate) fn pipe() -> (DuplexStream, DuplexStream) {
+    tokio::io::duplex(CAPACITY)
+}
+
+/// Spawns the task writing every frame sent on the returned channel to
+/// `writer`, coalescing the frames queued at the time of a write. Frames are
+/// traced to `tracer` as outbound as they are written.
+///
+/// The task stops, closing `writer`, once every sender has been dropped or a
+/// write fails.
+pub(crate) fn spawn_writer<W>(mut writer: W, tracer: Option<Tracer>) -> UnboundedSender<Vec<u8>>
+where
+    W: AsyncWrite + Send + Unpin + 'static,
+{
+    let (tx, mut rx) = unbounded_channel::<Vec<u8>>();
+
+    tokio::spawn(async move {
+        let mut buf = Vec::new();
+
+        while let Some(frame) = rx.recv().await {
+            buf.clear();
+            let mut next = Some(frame);
+            while let Some(frame) = next {
+                if let Some(tracer) = &tracer {
+                    tracer.record(Direction::Outbound, &codec::to_json(&frame));
+                }
+                buf.extend(frame);
+                next = rx.try_recv().ok();
+            }
+
+            if writer.write_all(&buf).await.is_err() || writer.flush().await.is_err() {
+                break;
+            }
+        }
+    });
+
+    tx
+}
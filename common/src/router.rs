@@ -0,0 +1,82 @@
+//! Dispatch of messages to handlers registered by message type for
+//! [`RoutedNode`](crate::node::RoutedNode)s, as an alternative to matching on
+//! every payload in [`Node::handle_message`](crate::node::Node::handle_message).
+
+use std::collections::HashMap;
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::codec;
+use crate::message::Message;
+
+/// Maelstrom's error code for messages of a type a node does not support
+pub const NOT_SUPPORTED: u32 = 10;
+
+type Handler<N> = Box<dyn Fn(&mut N, &[u8]) + Send>;
+
+/// The message type of a message, decoded without its payload
+#[derive(Debug, Deserialize)]
+pub(crate) struct TypeName {
+    #[serde(rename = "type")]
+    pub(crate) name: String,
+}
+
+/// Handlers for the messages a node of type `N` receives, by message type
+pub struct Router<N> {
+    handlers: HashMap<String, Handler<N>>,
+}
+
+impl<N> Default for Router<N> {
+    fn default() -> Self {
+        Self {
+            handlers: HashMap::new(),
+        }
+    }
+}
+
+impl<N> Router<N> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hands messages of type `type_name` to `handler`, with their payloads
+    /// decoded as a `P`. Each type may have its own payload, such as a struct
+    /// with just the fields of that type.
+    pub fn handle<P, F>(&mut self, type_name: &str, handler: F) -> &mut Self
+    where
+        P: DeserializeOwned,
+        F: Fn(&mut N, Message<P>) + Send + 'static,
+    {
+        let handler = move |node: &mut N, frame: &[u8]| {
+            let message =
+                codec::decode(frame).expect("failed to deserialize frame from input stream!");
+            handler(node, message);
+        };
+
+        let replaced = self
+            .handlers
+            .insert(type_name.to_string(), Box::new(handler));
+        assert!(replaced.is_none(), "{type_name} already has a handler");
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+
+    /// Hands `frame` to the handler for its message type, or returns the
+    /// message without its payload if there is none
+    pub(crate) fn dispatch(&self, node: &mut N, frame: &[u8]) -> Result<(), Message<TypeName>> {
+        let message: Message<TypeName> =
+            codec::decode(frame).expect("failed to deserialize frame from input stream!");
+
+        match self.handlers.get(&message.body.payload.name) {
+            Some(handler) => {
+                handler(node, frame);
+                Ok(())
+            }
+            None => Err(message),
+        }
+    }
+}
@@ -1,100 +1,686 @@
-use crate::message::{Message, MessageBody};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::codec::{self, Codec};
+use crate::message::{Message, MessageBody, MessageId};
+use crate::middleware::{Dedup, Inbound, Layer, Outbound, Stack};
 use serde::{Deserialize, Serialize};
-use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
-use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::io::{AsyncRead, AsyncWrite, BufReader};
+use tokio::sync::mpsc;
+
+use crate::node::{ConcurrentNode, Node, NodeId, RoutedNode};
+use crate::outbox::{self, Outbox, Overflow};
+use crate::router::{self, Router, TypeName};
+use crate::trace::{self, Direction, ReplayDiff, TraceEntry, Tracer};
+use crate::transport::{TcpConfig, TcpTransport};
+use crate::writer::MessageWriter;
+
+/// The environment variable naming a file to record a trace to
+const TRACE_VAR: &str = "RUNTIME_TRACE";
+
+/// The environment variable naming a recorded trace to replay instead of
+/// reading from the input stream
+const REPLAY_VAR: &str = "RUNTIME_REPLAY";
+
+/// The environment variables configuring the outbox of the node
+const OUTBOX_CAPACITY_VAR: &str = "RUNTIME_OUTBOX_CAPACITY";
+const OUTBOX_OVERFLOW_VAR: &str = "RUNTIME_OUTBOX_OVERFLOW";
+
+/// The environment variable setting how often, in milliseconds, the metrics of
+/// the node's outbox are reported on stderr
+const METRICS_INTERVAL_VAR: &str = "RUNTIME_METRICS_INTERVAL";
 
-use crate::node::Node;
+/// The environment variable setting how many workers handle messages for
+/// concurrent nodes
+const WORKERS_VAR: &str = "RUNTIME_WORKERS";
+
+/// The environment variable enabling deduplication of the messages the node
+/// receives, over a window of as many messages as it is set to
+const DEDUP_WINDOW_VAR: &str = "RUNTIME_DEDUP_WINDOW";
+
+/// The environment variables configuring the node to exchange messages over
+/// TCP instead of the input and output streams
+const TCP_CONFIG_VAR: &str = "RUNTIME_TCP_CONFIG";
+const NODE_ID_VAR: &str = "RUNTIME_NODE_ID";
+
+/// How many messages a node can send before the writer catches up, by default
+const DEFAULT_OUTBOX_CAPACITY: usize = 1024;
+
+/// How many messages can be dispatched to a worker before the reader waits
+/// for it to catch up
+const WORKER_QUEUE_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone)]
+pub struct Runtime {
+    tracer: Option<Tracer>,
+    outbox_capacity: usize,
+    overflow: Overflow,
+    metrics_interval: Option<Duration>,
+    workers: usize,
+    codec: Codec,
+    layers: Stack,
+}
 
-pub struct Runtime;
+impl Default for Runtime {
+    fn default() -> Self {
+        Self {
+            tracer: None,
+            outbox_capacity: DEFAULT_OUTBOX_CAPACITY,
+            overflow: Overflow::default(),
+            metrics_interval: None,
+            workers: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            codec: Codec::default(),
+            layers: Stack::default(),
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 #[serde(rename_all = "snake_case")]
-enum BootstrapPayload {
+enum RuntimePayload {
     Init {
         node_id: String,
         node_ids: Vec<String>,
     },
     InitOk,
+    Error {
+        code: u32,
+        text: String,
+    },
+}
+
+/// Where the runtime reads frames from
+enum Input<R> {
+    Stream(BufReader<R>),
+    /// The frames that made it through the middleware
+    Layered(Inbound),
+}
+
+/// Where the runtime writes frames to
+enum Output<W> {
+    Stream(W),
+    /// The middleware, which writes the frames that make it through
+    Layered(Outbound),
 }
 
 impl Runtime {
-    /// Starts delegating processing of messages as they arrive on the specified `reader`
-    pub async fn start<N, R, W>(reader: R, mut writer: W)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records every line read and written to `tracer`
+    pub fn trace(mut self, tracer: Tracer) -> Self {
+        self.tracer = Some(tracer);
+        self
+    }
+
+    /// Limits the node's outbox to `capacity` messages, applying `overflow`
+    /// once it is full
+    pub fn outbox(mut self, capacity: usize, overflow: Overflow) -> Self {
+        self.outbox_capacity = capacity;
+        self.overflow = overflow;
+        self
+    }
+
+    /// Reports the [`Metrics`](crate::outbox::Metrics) of the node's outbox on
+    /// stderr every `interval`
+    pub fn report_metrics(mut self, interval: Duration) -> Self {
+        self.metrics_interval = Some(interval);
+        self
+    }
+
+    /// Handles messages for [`ConcurrentNode`]s on `workers` tasks
+    pub fn workers(mut self, workers: usize) -> Self {
+        assert!(workers > 0, "at least one worker is required");
+        self.workers = workers;
+        self
+    }
+
+    /// Encodes messages to other nodes with `codec`, which is only understood
+    /// by nodes, not by Maelstrom. Messages to clients are always JSON, and
+    /// messages of any codec are read.
+    pub fn codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Adds `layer` to the node's middleware. Messages the node receives pass
+    /// through layers in the order they were added, and messages it sends in
+    /// the reverse order.
+    pub fn layer(mut self, layer: impl Layer) -> Self {
+        self.layers.push(layer);
+        self
+    }
+
+    /// Starts delegating processing of messages as they arrive on the specified `reader`.
+    ///
+    /// If `RUNTIME_TRACE` is set, a trace is recorded to the file it names,
+    /// with any `{pid}` replaced by the process ID so the nodes of a cluster
+    /// can share one setting. If `RUNTIME_REPLAY` is set, the trace it names
+    /// is replayed instead and any divergence from the recorded output is
+    /// reported. `RUNTIME_OUTBOX_CAPACITY` and `RUNTIME_OUTBOX_OVERFLOW`
+    /// (`block`, `drop-oldest` or `reject`) configure the node's outbox, and
+    /// if `RUNTIME_METRICS_INTERVAL` is set, its metrics are reported on
+    /// stderr every that many milliseconds. If
+    /// `RUNTIME_DEDUP_WINDOW` is set, repeats of the last that many messages
+    /// are filtered out by a [`Dedup`] layer.
+    ///
+    /// If `RUNTIME_TCP_CONFIG` names a [`TcpConfig`] file, the node instead
+    /// exchanges messages over TCP as the node named by `RUNTIME_NODE_ID`,
+    /// with other nodes in the codec the config names, and `reader` and
+    /// `writer` are unused.
+    pub async fn start<N, R, W>(reader: R, writer: W)
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+        W: AsyncWrite + Send + Unpin + 'static,
+        N: for<'a> Node<'a> + 'static,
+    {
+        Self::new().start_with::<N, R, W>(reader, writer).await
+    }
+
+    /// Like [`Runtime::start`], but configured from the environment on top of
+    /// this runtime, for nodes that bring layers of their own
+    pub async fn start_with<N, R, W>(self, reader: R, writer: W)
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+        W: AsyncWrite + Send + Unpin + 'static,
+        N: for<'a> Node<'a> + 'static,
+    {
+        if let Ok(path) = std::env::var(REPLAY_VAR) {
+            let trace = read_trace_file(&path);
+            return report_replay(self.untraced_with_env().replay::<N>(&trace).await);
+        }
+
+        let runtime = self.with_env();
+        match tcp_from_env().await {
+            Some(transport) => {
+                let runtime = runtime.codec(transport.codec());
+                transport
+                    .serve(|reader, writer| runtime.run::<N, _, _>(reader, writer))
+                    .await
+            }
+            None => runtime.run::<N, R, W>(reader, writer).await,
+        }
+    }
+
+    /// Like [`Runtime::start`], but handles messages concurrently on a pool of
+    /// workers, sized by `RUNTIME_WORKERS` or the available parallelism.
+    /// Replaying traces is not supported.
+    pub async fn start_concurrent<N, R, W>(reader: R, writer: W)
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+        W: AsyncWrite + Send + Unpin + 'static,
+        N: ConcurrentNode + 'static,
+    {
+        let runtime = Self::new().with_env();
+        match tcp_from_env().await {
+            Some(transport) => {
+                let runtime = runtime.codec(transport.codec());
+                transport
+                    .serve(|reader, writer| runtime.run_concurrent::<N, _, _>(reader, writer))
+                    .await
+            }
+            None => runtime.run_concurrent::<N, R, W>(reader, writer).await,
+        }
+    }
+
+    /// Like [`Runtime::start`], but for a [`RoutedNode`], whose messages are
+    /// handed to the handler registered for their type
+    pub async fn start_routed<N, R, W>(reader: R, writer: W)
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+        W: AsyncWrite + Send + Unpin + 'static,
+        N: RoutedNode + 'static,
+    {
+        if let Ok(path) = std::env::var(REPLAY_VAR) {
+            let trace = read_trace_file(&path);
+            return report_replay(
+                Self::new()
+                    .untraced_with_env()
+                    .replay_routed::<N>(&trace)
+                    .await,
+            );
+        }
+
+        let runtime = Self::new().with_env();
+        match tcp_from_env().await {
+            Some(transport) => {
+                let runtime = runtime.codec(transport.codec());
+                transport
+                    .serve(|reader, writer| runtime.run_routed::<N, _, _>(reader, writer))
+                    .await
+            }
+            None => runtime.run_routed::<N, R, W>(reader, writer).await,
+        }
+    }
+
+    /// Applies the configuration in the environment to this runtime
+    fn with_env(self) -> Self {
+        let runtime = self.untraced_with_env();
+
+        match std::env::var(TRACE_VAR) {
+            Ok(path) => {
+                let path = path.replace("{pid}", &std::process::id().to_string());
+                runtime.trace(Tracer::create(path).expect("failed creating trace file"))
+            }
+            Err(_) => runtime,
+        }
+    }
+
+    /// Like [`Runtime::with_env`], but never records a trace, for replaying
+    /// one with the same middleware it was recorded with
+    fn untraced_with_env(self) -> Self {
+        let mut runtime = self;
+
+        if let Ok(capacity) = std::env::var(OUTBOX_CAPACITY_VAR) {
+            runtime.outbox_capacity = capacity.parse().expect("invalid outbox capacity");
+        }
+        if let Ok(overflow) = std::env::var(OUTBOX_OVERFLOW_VAR) {
+            runtime.overflow = overflow.parse().expect("invalid outbox overflow policy");
+        }
+        if let Ok(interval) = std::env::var(METRICS_INTERVAL_VAR) {
+            let millis = interval.parse().expect("invalid metrics interval");
+            runtime = runtime.report_metrics(Duration::from_millis(millis));
+        }
+        if let Ok(workers) = std::env::var(WORKERS_VAR) {
+            runtime = runtime.workers(workers.parse().expect("invalid worker count"));
+        }
+        if let Ok(window) = std::env::var(DEDUP_WINDOW_VAR) {
+            runtime = runtime.layer(Dedup::new(window.parse().expect("invalid dedup window")));
+        }
+
+        runtime
+    }
+
+    /// Like [`Runtime::start`], but ignores the environment
+    pub async fn run<N, R, W>(mut self, reader: R, writer: W)
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+        W: AsyncWrite + Send + Unpin + 'static,
+        N: for<'a> Node<'a> + 'static,
+    {
+        let (input, output) = self.connect(reader, writer);
+        self.run_node::<N, _, _>(input, output).await
+    }
+
+    /// Like [`Runtime::start_concurrent`], but ignores the environment
+    pub async fn run_concurrent<N, R, W>(mut self, reader: R, writer: W)
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+        W: AsyncWrite + Send + Unpin + 'static,
+        N: ConcurrentNode + 'static,
+    {
+        let (input, output) = self.connect(reader, writer);
+        self.run_concurrent_node::<N, _, _>(input, output).await
+    }
+
+    /// Like [`Runtime::start_routed`], but ignores the environment
+    pub async fn run_routed<N, R, W>(mut self, reader: R, writer: W)
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+        W: AsyncWrite + Send + Unpin + 'static,
+        N: RoutedNode + 'static,
+    {
+        let (input, output) = self.connect(reader, writer);
+        self.run_routed_node::<N, _, _>(input, output).await
+    }
+
+    /// Replays `trace` like [`trace::replay`], but behind this runtime's
+    /// middleware, which must match the middleware it was recorded with
+    pub async fn replay<N>(self, trace: &[TraceEntry]) -> ReplayDiff
+    where
+        N: for<'a> Node<'a> + 'static,
+    {
+        trace::replay_with(trace, |stdin, stdout| self.run::<N, _, _>(stdin, stdout)).await
+    }
+
+    /// Like [`Runtime::replay`], but for a [`RoutedNode`]
+    pub async fn replay_routed<N>(self, trace: &[TraceEntry]) -> ReplayDiff
+    where
+        N: RoutedNode + 'static,
+    {
+        trace::replay_with(trace, |stdin, stdout| {
+            self.run_routed::<N, _, _>(stdin, stdout)
+        })
+        .await
+    }
+
+    /// Interposes any middleware between the node and `reader` and `writer`.
+    /// Frames are then traced as the middleware reads and writes them, so a
+    /// trace records the streams as they were.
+    fn connect<R, W>(&mut self, reader: R, writer: W) -> (Input<R>, Output<W>)
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+        W: AsyncWrite + Send + Unpin + 'static,
+    {
+        if self.layers.is_empty() {
+            return (
+                Input::Stream(BufReader::new(reader)),
+                Output::Stream(writer),
+            );
+        }
+
+        let (inbound, outbound) = self.layers.wrap(reader, writer, self.tracer.take());
+        (Input::Layered(inbound), Output::Layered(outbound))
+    }
+
+    /// Runs `N` on `reader` and `writer`, after any middleware
+    async fn run_node<N, R, W>(self, mut reader: Input<R>, writer: Output<W>)
     where
         R: AsyncRead + Unpin,
         W: AsyncWrite + Send + Unpin + 'static,
         N: for<'a> Node<'a> + 'static,
     {
-        let reader = BufReader::new(reader);
-        let mut lines = reader.lines();
-        let (bootstrap_tx, bootstrap_rx) =
-            tokio::sync::mpsc::unbounded_channel::<Message<BootstrapPayload>>();
-        let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<Message<N::Payload>>();
-
-        tokio::spawn(async move {
-            async fn write_loop<W, T>(writer: &mut W, mut rx: UnboundedReceiver<T>)
-            where
-                T: Serialize,
-                W: AsyncWrite + Send + Unpin + 'static,
-            {
-                while let Some(message) = rx.recv().await {
-                    let mut bytes =
-                        serde_json::to_vec(&message).expect("failed serializing message");
-                    bytes.extend(b"\n");
-                    writer.write_all(&bytes).await.expect("failed writing buf");
+        // Reused for every frame, as payloads may borrow from it while they
+        // are handled
+        let mut frame = Vec::new();
+
+        let (mut n, _) = self
+            .init(&mut reader, &mut frame, writer, |node_id, neighbors, tx| {
+                let mut n = <N as Node<'static>>::from_init(node_id, neighbors, tx);
+                let msg_id = n.next_msg_id();
+                (n, msg_id)
+            })
+            .await;
+
+        while self.read_frame(&mut reader, &mut frame).await {
+            handle_frame(&mut n, &frame);
+        }
+    }
+
+    /// Runs `N` on `reader` and `writer`, dispatching messages to the
+    /// handlers it registers, after any middleware
+    async fn run_routed_node<N, R, W>(self, mut reader: Input<R>, writer: Output<W>)
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Send + Unpin + 'static,
+        N: RoutedNode + 'static,
+    {
+        let mut frame = Vec::new();
+
+        let (mut n, control_tx) = self
+            .init(&mut reader, &mut frame, writer, |node_id, neighbors, tx| {
+                let mut n = N::from_init(node_id, neighbors, tx);
+                let msg_id = n.next_msg_id();
+                (n, msg_id)
+            })
+            .await;
+
+        let mut router = Router::new();
+        N::route(&mut router);
+
+        while self.read_frame(&mut reader, &mut frame).await {
+            let Err(message) = router.dispatch(&mut n, &frame) else {
+                continue;
+            };
+
+            // Answering replies or errors could bounce errors between nodes
+            // indefinitely, so only requests are answered
+            if message.body.in_reply_to.is_none() && message.body.payload.name != "error" {
+                control_tx.send_or_drop(not_supported(message));
+            }
+        }
+    }
+
+    /// Runs `N` on `reader` and `writer` with a pool of workers, after any
+    /// middleware
+    async fn run_concurrent_node<N, R, W>(self, mut reader: Input<R>, writer: Output<W>)
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Send + Unpin + 'static,
+        N: ConcurrentNode + 'static,
+    {
+        let mut frame = Vec::new();
+
+        let (node, _) = self
+            .init(&mut reader, &mut frame, writer, |node_id, neighbors, tx| {
+                let n = N::from_init(node_id, neighbors, tx);
+                let msg_id = n.next_msg_id();
+                (n, msg_id)
+            })
+            .await;
+        let node = Arc::new(node);
+
+        let (queues, handles): (Vec<_>, Vec<_>) = (0..self.workers)
+            .map(|_| {
+                let (queue, mut queue_rx) = mpsc::channel(WORKER_QUEUE_CAPACITY);
+                let node = Arc::clone(&node);
+
+                let handle = tokio::spawn(async move {
+                    while let Some(message) = queue_rx.recv().await {
+                        node.handle_message(message);
+                    }
+                });
+
+                (queue, handle)
+            })
+            .unzip();
+
+        let mut next_worker = 0;
+
+        while self.read_frame(&mut reader, &mut frame).await {
+            let message: Message<N::Payload> =
+                codec::decode(&frame).expect("failed to deserialize frame from input stream!");
+
+            // Messages without a key are spread across workers in turn
+            let worker = match node.dispatch_key(&message) {
+                Some(key) => (key % self.workers as u64) as usize,
+                None => {
+                    next_worker = (next_worker + 1) % self.workers;
+                    next_worker
                 }
+            };
+
+            if queues[worker].send(message).await.is_err() {
+                panic!("worker stopped");
             }
+        }
+
+        // Let workers finish handling the messages already dispatched to them
+        drop(queues);
+        for handle in handles {
+            handle.await.expect("worker panicked");
+        }
+    }
 
-            write_loop(&mut writer, bootstrap_rx).await;
-            write_loop(&mut writer, rx).await;
-        });
+    /// Spawns the task writing messages to `writer`, returning the outbox for
+    /// the runtime's own messages, starting with the `init_ok` reply, and the
+    /// outbox for the node. Messages to `servers` are encoded in the
+    /// runtime's codec.
+    fn spawn_writer<P, W>(
+        &self,
+        writer: Output<W>,
+        servers: &[NodeId],
+    ) -> (Outbox<RuntimePayload>, Outbox<P>)
+    where
+        P: Serialize + Send + 'static,
+        W: AsyncWrite + Send + Unpin + 'static,
+    {
+        let (control_tx, control_rx) = outbox::channel(self.outbox_capacity, self.overflow);
+        let (tx, rx) = outbox::channel(self.outbox_capacity, self.overflow);
 
-        // Handle init message
-        let startup = lines
-            .next_line()
-            .await
-            .expect("error reading from input stream!");
-        let mut n: N;
-
-        if let Some(init) = startup {
-            let message: Message<BootstrapPayload> =
-                serde_json::from_str(init.as_str()).expect("failed to deserialize init message!");
-            match message.body.payload {
-                BootstrapPayload::Init { node_id, node_ids } => {
-                    n = N::from_init(
-                        node_id.clone(),
-                        node_ids.into_iter().filter(|id| *id != node_id).collect(),
-                        tx.clone(),
-                    );
-                    let next_id = n.next_msg_id();
-                    bootstrap_tx
-                        .send(Message {
-                            src: node_id,
-                            dest: message.src,
-                            body: MessageBody {
-                                msg_id: Some(next_id),
-                                in_reply_to: message.body.msg_id,
-                                payload: BootstrapPayload::InitOk,
-                            },
-                        })
-                        .expect("failed to send init ok message");
-                    drop(bootstrap_tx);
+        if let Some(interval) = self.metrics_interval {
+            let metrics = rx.metrics_handle();
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(interval).await;
+                    let Some(metrics) = metrics.get() else {
+                        break;
+                    };
+                    eprintln!("outbox: {metrics}");
                 }
-                _ => panic!("first message was not init message"),
+            });
+        }
+
+        match writer {
+            Output::Stream(writer) => {
+                let writer = MessageWriter::new(writer)
+                    .codec(self.codec)
+                    .servers(servers.iter().cloned())
+                    .trace(self.tracer.clone());
+                tokio::spawn(drain(writer, control_rx, rx));
+            }
+            Output::Layered(outbound) => {
+                let writer = MessageWriter::new(tokio::io::sink())
+                    .codec(self.codec)
+                    .servers(servers.iter().cloned())
+                    .layers(outbound);
+                tokio::spawn(drain(writer, control_rx, rx));
             }
-        } else {
+        }
+
+        (control_tx, tx)
+    }
+
+    /// Handles the init message, spawning the writer to `writer` for the
+    /// cluster it names and creating the node with `from_init`, which also
+    /// returns the message ID to reply with. Returns the node and the outbox
+    /// for the runtime's own messages.
+    async fn init<R, W, P, T>(
+        &self,
+        reader: &mut Input<R>,
+        frame: &mut Vec<u8>,
+        writer: Output<W>,
+        from_init: impl FnOnce(NodeId, Vec<NodeId>, Outbox<P>) -> (T, MessageId),
+    ) -> (T, Outbox<RuntimePayload>)
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Send + Unpin + 'static,
+        P: Serialize + Send + 'static,
+    {
+        if !self.read_frame(reader, frame).await {
             panic!("expected init message")
         }
 
-        while let Some(line) = lines
-            .next_line()
-            .await
-            .expect("error reading from input stream!")
-        {
-            let message: Message<N::Payload> = serde_json::from_str(line.as_str())
-                .expect("failed to deserialize line from input stream!");
-            n.handle_message(message);
+        let message: Message<RuntimePayload> =
+            codec::decode(frame).expect("failed to deserialize init message!");
+        let RuntimePayload::Init { node_id, node_ids } = message.body.payload else {
+            panic!("first message was not init message")
+        };
+
+        let (control_tx, tx) = self.spawn_writer(writer, &node_ids);
+        let (n, next_id) = from_init(
+            node_id.clone(),
+            node_ids.into_iter().filter(|id| *id != node_id).collect(),
+            tx,
+        );
+        control_tx
+            .send(Message {
+                src: node_id,
+                dest: message.src,
+                body: MessageBody {
+                    msg_id: Some(next_id),
+                    in_reply_to: message.body.msg_id,
+                    payload: RuntimePayload::InitOk,
+                },
+            })
+            .expect("failed to send init ok message");
+
+        (n, control_tx)
+    }
+
+    /// Reads the next frame into `frame`, returning `false` at the end of the
+    /// input stream
+    async fn read_frame<R>(&self, reader: &mut Input<R>, frame: &mut Vec<u8>) -> bool
+    where
+        R: AsyncRead + Unpin,
+    {
+        let read = match reader {
+            Input::Stream(reader) => codec::read_frame(reader, frame).await,
+            Input::Layered(frames) => match frames.recv().await {
+                Some(next) => next.map(|next| {
+                    *frame = next;
+                    true
+                }),
+                None => Ok(false),
+            },
         }
+        .expect("error reading from input stream!");
+
+        if let Some(tracer) = &self.tracer {
+            if read {
+                tracer.record(Direction::Inbound, &codec::to_json(frame));
+            }
+        }
+
+        read
+    }
+}
+
+/// Writes the `init_ok` reply queued on `control_rx`, then every message
+/// queued on either outbox
+async fn drain<C, P, W>(
+    mut writer: MessageWriter<W>,
+    mut control_rx: outbox::Receiver<C>,
+    mut rx: outbox::Receiver<P>,
+) where
+    C: Serialize,
+    P: Serialize,
+    W: AsyncWrite + Unpin,
+{
+    // The node may send messages as soon as it is created, but they must not
+    // be written before the init_ok reply
+    if let Some(init_ok) = control_rx.recv().await {
+        writer.write_message(&init_ok).await;
+    }
+    writer.drain_with(&mut control_rx, &mut rx).await;
+}
+
+/// The reply to a message of a type the node has no handler for
+fn not_supported(message: Message<TypeName>) -> Message<RuntimePayload> {
+    Message {
+        src: message.dest,
+        dest: message.src,
+        body: MessageBody {
+            msg_id: None,
+            in_reply_to: message.body.msg_id,
+            payload: RuntimePayload::Error {
+                code: router::NOT_SUPPORTED,
+                text: format!("{} is not supported", message.body.payload.name),
+            },
+        },
+    }
+}
+
+/// Binds the TCP transport configured by the environment, if any
+async fn tcp_from_env() -> Option<TcpTransport> {
+    let path = std::env::var(TCP_CONFIG_VAR).ok()?;
+    let config = TcpConfig::read(path).expect("failed reading TCP config");
+    let node_id =
+        std::env::var(NODE_ID_VAR).expect("RUNTIME_NODE_ID is required with a TCP config");
+
+    Some(
+        TcpTransport::bind(config, node_id)
+            .await
+            .expect("failed binding TCP listener"),
+    )
+}
+
+/// Decodes `frame` into a message whose payload may borrow from it, and hands
+/// it to `node`
+fn handle_frame<'a, N: Node<'a>>(node: &mut N, frame: &'a [u8]) {
+    let message: Message<N::Payload> =
+        codec::decode(frame).expect("failed to deserialize frame from input stream!");
+    node.handle_message(message);
+}
+
+/// Reads the trace to replay from `path`
+fn read_trace_file(path: &str) -> Vec<TraceEntry> {
+    let file = std::fs::File::open(path).expect("failed opening trace file");
+    trace::read_trace(std::io::BufReader::new(file)).expect("failed reading trace file")
+}
+
+/// Reports every message a replay did not reproduce
+fn report_replay(diff: ReplayDiff) {
+    for message in &diff.missing {
+        eprintln!("- {message}");
+    }
+    for message in &diff.unexpected {
+        eprintln!("+ {message}");
+    }
+
+    if !diff.is_empty() {
+        panic!("replay diverged from trace");
     }
 }
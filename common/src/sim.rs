@@ -0,0 +1,236 @@
+//! An in-process cluster for exercising nodes without the Maelstrom harness.
+//!
+//! Every node runs on its own [`Runtime`], connected to an in-memory router by
+//! a pair of pipes standing in for stdin and stdout. Messages addressed to a
+//! node in the cluster are routed to it, and everything else is delivered to
+//! the client driven by test code. Messages between nodes can be subjected to
+//! [`Faults`] and [`Partition`]s, and a [`Simulation`] can run a cluster
+//! deterministically in virtual time.
+//!
+//! The [`Network`] underneath can also connect nodes run elsewhere, such as
+//! in other processes.
+
+mod simulation;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+use crate::message::{Message, MessageBody, MessageId};
+pub use crate::network::{Faults, Network, Partition};
+use crate::node::{Node, NodeId};
+use crate::pipe;
+use crate::runtime::Runtime;
+pub use simulation::Simulation;
+
+/// The node ID test code sends requests as
+const CLIENT_ID: &str = "c0";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+enum InitPayload {
+    Init {
+        node_id: NodeId,
+        node_ids: Vec<NodeId>,
+    },
+    InitOk,
+}
+
+pub struct Cluster {
+    node_ids: Vec<NodeId>,
+    curr_msg_id: MessageId,
+    network: Network,
+    client_rx: UnboundedReceiver<Message<Value>>,
+    /// Messages for the client received while awaiting a different reply
+    unclaimed: Vec<Message<Value>>,
+}
+
+impl Cluster {
+    /// Starts `size` nodes of type `N`, named `n0` through `n{size - 1}`, and
+    /// waits for each of them to complete the `init` handshake
+    pub async fn start<N>(size: usize) -> Self
+    where
+        N: for<'a> Node<'a> + Send + 'static,
+    {
+        Self::start_seeded::<N>(size, rand::random()).await
+    }
+
+    /// Like [`Cluster::start`], but draws network faults from an RNG seeded
+    /// with `seed`
+    pub async fn start_seeded<N>(size: usize, seed: u64) -> Self
+    where
+        N: for<'a> Node<'a> + Send + 'static,
+    {
+        let node_ids: Vec<NodeId> = (0..size).map(|i| format!("n{i}")).collect();
+        let (network, client_rx) = Network::start_seeded(seed);
+
+        for node_id in &node_ids {
+            network.join(node_id.clone(), spawn_node::<N>(network.clone()));
+        }
+
+        let mut cluster = Self {
+            node_ids: node_ids.clone(),
+            curr_msg_id: Default::default(),
+            network,
+            client_rx,
+            unclaimed: vec![],
+        };
+
+        for node_id in &node_ids {
+            let _: InitPayload = cluster
+                .request(
+                    node_id,
+                    InitPayload::Init {
+                        node_id: node_id.clone(),
+                        node_ids: node_ids.clone(),
+                    },
+                )
+                .await;
+        }
+
+        cluster
+    }
+
+    /// Starts a node of type `S` named `node_id` alongside the cluster's
+    /// nodes, such as a stand-in for one of Maelstrom's services. It is not
+    /// one of [`Cluster::node_ids`], and only learns of itself on `init`.
+    pub async fn add_node<S>(&mut self, node_id: &str)
+    where
+        S: for<'a> Node<'a> + Send + 'static,
+    {
+        self.network
+            .join(node_id.to_string(), spawn_node::<S>(self.network.clone()));
+
+        let _: InitPayload = self
+            .request(
+                node_id,
+                InitPayload::Init {
+                    node_id: node_id.to_string(),
+                    node_ids: vec![node_id.to_string()],
+                },
+            )
+            .await;
+    }
+
+    pub fn node_ids(&self) -> &[NodeId] {
+        &self.node_ids
+    }
+
+    /// Sends `payload` from the client to `dest`, returning the ID of the sent
+    /// message
+    pub fn send<P: Serialize>(&mut self, dest: &str, payload: P) -> MessageId {
+        self.curr_msg_id = self.curr_msg_id.checked_add(1).expect("id overflow");
+
+        self.network.send(Message {
+            src: CLIENT_ID.to_string(),
+            dest: dest.to_string(),
+            body: MessageBody {
+                msg_id: Some(self.curr_msg_id),
+                in_reply_to: None,
+                payload: serde_json::to_value(payload).expect("failed serializing payload"),
+            },
+        });
+
+        self.curr_msg_id
+    }
+
+    /// Applies `faults` to every message subsequently sent between nodes
+    ///
+    /// # Panics
+    ///
+    /// If any probability in `faults` is not between 0 and 1
+    pub fn set_faults(&self, faults: Faults) {
+        self.network.set_faults(faults);
+    }
+
+    /// Replaces the current partition, if any, with `partition`
+    pub fn partition(&self, partition: Partition) {
+        self.network.partition(partition);
+    }
+
+    /// Heals any partition between nodes
+    pub fn heal(&self) {
+        self.partition(Partition::default());
+    }
+
+    /// Waits for the next message sent to the client
+    pub async fn recv<R: DeserializeOwned>(&mut self) -> Message<R> {
+        let message = if self.unclaimed.is_empty() {
+            self.client_rx.recv().await.expect("router stopped")
+        } else {
+            self.unclaimed.remove(0)
+        };
+
+        decode(message)
+    }
+
+    /// Waits for the reply to the client message `msg_id`
+    pub async fn reply_to<R: DeserializeOwned>(&mut self, msg_id: MessageId) -> Message<R> {
+        let is_reply = |message: &Message<Value>| message.body.in_reply_to == Some(msg_id);
+
+        let message = match self.unclaimed.iter().position(is_reply) {
+            Some(index) => self.unclaimed.remove(index),
+            None => loop {
+                let message = self.client_rx.recv().await.expect("router stopped");
+
+                if is_reply(&message) {
+                    break message;
+                }
+                self.unclaimed.push(message);
+            },
+        };
+
+        decode(message)
+    }
+
+    /// Sends `payload` from the client to `dest` and waits for the reply
+    pub async fn request<P, R>(&mut self, dest: &str, payload: P) -> R
+    where
+        P: Serialize,
+        R: DeserializeOwned,
+    {
+        let msg_id = self.send(dest, payload);
+        self.reply_to(msg_id).await.body.payload
+    }
+}
+
+/// Deserializes the payload of a message received by the client
+fn decode<R: DeserializeOwned>(message: Message<Value>) -> Message<R> {
+    Message {
+        src: message.src,
+        dest: message.dest,
+        body: MessageBody {
+            msg_id: message.body.msg_id,
+            in_reply_to: message.body.in_reply_to,
+            payload: serde_json::from_value(message.body.payload)
+                .expect("failed to deserialize reply"),
+        },
+    }
+}
+
+/// Runs a node of type `N`, returning the sender for its inbox, which takes
+/// frames. Messages the node writes are sent on `network`.
+fn spawn_node<N>(network: Network) -> UnboundedSender<Vec<u8>>
+where
+    N: for<'a> Node<'a> + Send + 'static,
+{
+    let (stdin, stdin_writer) = pipe::pipe();
+    let (stdout, stdout_reader) = pipe::pipe();
+    let inbox = pipe::spawn_writer(stdin_writer, None);
+
+    tokio::spawn(Runtime::new().run::<N, _, _>(stdin, stdout));
+
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(stdout_reader).lines();
+
+        while let Ok(Some(line)) = lines.next_line().await {
+            let message = serde_json::from_str(&line).expect("node wrote malformed message");
+            network.send(message);
+        }
+    });
+
+    inbox
+}
@@ -0,0 +1,74 @@
+use std::future::Future;
+
+use super::Cluster;
+use crate::node::Node;
+
+/// The environment variable a failing seed can be replayed from
+const SEED_VAR: &str = "SIM_SEED";
+
+/// A deterministic simulation of a cluster.
+///
+/// Simulations run on a single-threaded runtime whose clock is paused, so
+/// every timer a node sets fires in virtual time, which advances only when all
+/// tasks are idle. Combined with a network whose faults are drawn from a
+/// seeded RNG, the same seed always produces the same execution.
+#[derive(Debug, Clone, Copy)]
+pub struct Simulation {
+    seed: u64,
+}
+
+impl Simulation {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    /// Creates a simulation seeded from the `SIM_SEED` environment variable,
+    /// or with a random seed if it is unset
+    pub fn from_env() -> Self {
+        let seed = match std::env::var(SEED_VAR) {
+            Ok(seed) => seed.parse().expect("SIM_SEED is not a valid seed"),
+            Err(_) => rand::random(),
+        };
+
+        Self::new(seed)
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Starts a cluster whose network is driven by this simulation's seed
+    pub async fn cluster<N>(&self, size: usize) -> Cluster
+    where
+        N: for<'a> Node<'a> + Send + 'static,
+    {
+        Cluster::start_seeded::<N>(size, self.seed).await
+    }
+
+    /// Runs `test` to completion in virtual time. If it panics, the seed is
+    /// reported so the failure can be replayed.
+    pub fn run<F, Fut>(&self, test: F) -> Fut::Output
+    where
+        F: FnOnce(Self) -> Fut,
+        Fut: Future,
+    {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .start_paused(true)
+            .build()
+            .expect("failed building runtime");
+
+        let _report = ReportSeedOnPanic(self.seed);
+        runtime.block_on(test(*self))
+    }
+}
+
+struct ReportSeedOnPanic(u64);
+
+impl Drop for ReportSeedOnPanic {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            eprintln!("simulation failed, replay with {SEED_VAR}={}", self.0);
+        }
+    }
+}
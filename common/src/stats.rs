@@ -0,0 +1,202 @@
+//! Latency and message count statistics, in the terms the Maelstrom
+//! challenges are graded in.
+//!
+//! The nodes named by `init` messages are servers, and every other
+//! participant, such as a client or a service like `seq-kv`, is not.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::message::{Message, MessageId};
+use crate::node::NodeId;
+use crate::trace::{Direction, TraceEntry};
+
+/// Accumulates statistics from messages as they are observed
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    /// When each outstanding client request was sent, keyed by client and
+    /// message ID
+    pending: HashMap<(NodeId, MessageId), u64>,
+    /// Nanoseconds between each client request and its reply
+    latencies: Vec<u64>,
+    by_type: BTreeMap<String, u64>,
+    by_node: BTreeMap<NodeId, u64>,
+    servers: HashSet<NodeId>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Names the nodes of the cluster, as opposed to clients and services.
+    /// Nodes named by `init` messages observed are added to them.
+    pub fn servers(mut self, servers: impl IntoIterator<Item = NodeId>) -> Self {
+        self.servers.extend(servers);
+        self
+    }
+
+    /// Records `message`, which was sent or received `time` nanoseconds after
+    /// some fixed point. Each message must be observed exactly once.
+    pub fn observe(&mut self, time: u64, message: &Message<Value>) {
+        if message.type_name() == Some("init") {
+            let node_ids = message.body.payload["node_ids"].as_array();
+            let node_ids = node_ids.into_iter().flatten().filter_map(Value::as_str);
+            self.servers.extend(node_ids.map(str::to_string));
+        }
+
+        match (self.is_server(&message.src), self.is_server(&message.dest)) {
+            (false, true) => {
+                if let Some(msg_id) = message.body.msg_id {
+                    self.pending.insert((message.src.clone(), msg_id), time);
+                }
+            }
+            (true, false) => {
+                let sent = message
+                    .body
+                    .in_reply_to
+                    .and_then(|msg_id| self.pending.remove(&(message.dest.clone(), msg_id)));
+
+                if let Some(sent) = sent {
+                    self.latencies.push(time.saturating_sub(sent));
+                }
+            }
+            (true, true) => {
+                let kind = message.type_name().unwrap_or("unknown").to_string();
+
+                *self.by_type.entry(kind).or_default() += 1;
+                *self.by_node.entry(message.src.clone()).or_default() += 1;
+            }
+            (false, false) => {}
+        }
+    }
+
+    /// Records the client requests a node received and every message it sent,
+    /// from a trace recorded by that node. Latencies are measured from when
+    /// the node received a request to when it replied.
+    pub fn observe_trace(&mut self, trace: &[TraceEntry]) {
+        for entry in trace {
+            let Ok(message) = serde_json::from_value::<Message<Value>>(entry.message.clone())
+            else {
+                continue;
+            };
+
+            // Messages between servers are counted when they are sent, as
+            // the receiver's trace records them too
+            let received_from_server =
+                entry.direction == Direction::Inbound && self.is_server(&message.src);
+
+            if !received_from_server {
+                self.observe(entry.time, &message);
+            }
+        }
+    }
+
+    fn is_server(&self, id: &str) -> bool {
+        self.servers.contains(id)
+    }
+
+    pub fn stats(&self) -> Stats {
+        let mut latencies = self.latencies.clone();
+        latencies.sort_unstable();
+
+        Stats {
+            operations: latencies.len() as u64,
+            latencies: Latencies::from_sorted(&latencies),
+            by_type: self.by_type.clone(),
+            by_node: self.by_node.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Latencies {
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl Latencies {
+    /// Computes nearest-rank percentiles of `sorted`, a sorted list of
+    /// latencies in nanoseconds, or `None` if it is empty
+    fn from_sorted(sorted: &[u64]) -> Option<Self> {
+        let percentile = |p: usize| {
+            let rank = (sorted.len() * p).div_ceil(100).max(1);
+            Duration::from_nanos(sorted[rank - 1])
+        };
+
+        (!sorted.is_empty()).then(|| Self {
+            p50: percentile(50),
+            p95: percentile(95),
+            p99: percentile(99),
+            max: percentile(100),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    /// Client requests that received a reply
+    pub operations: u64,
+    /// Latencies of client requests that received a reply
+    pub latencies: Option<Latencies>,
+    /// Messages sent between servers, by payload type
+    pub by_type: BTreeMap<String, u64>,
+    /// Messages sent between servers, by sender
+    pub by_node: BTreeMap<NodeId, u64>,
+}
+
+impl Stats {
+    /// The total number of messages sent between servers
+    pub fn server_messages(&self) -> u64 {
+        self.by_type.values().sum()
+    }
+
+    /// Messages sent between servers per client operation
+    pub fn msgs_per_op(&self) -> f64 {
+        self.per_op(self.server_messages())
+    }
+
+    fn per_op(&self, messages: u64) -> f64 {
+        if self.operations == 0 {
+            0.0
+        } else {
+            messages as f64 / self.operations as f64
+        }
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "operations: {}", self.operations)?;
+
+        if let Some(latencies) = &self.latencies {
+            writeln!(
+                f,
+                "latency: p50 {:?}, p95 {:?}, p99 {:?}, max {:?}",
+                latencies.p50, latencies.p95, latencies.p99, latencies.max
+            )?;
+        }
+
+        writeln!(
+            f,
+            "server messages: {} ({:.2} per op)",
+            self.server_messages(),
+            self.msgs_per_op()
+        )?;
+        for (kind, count) in &self.by_type {
+            writeln!(f, "  {kind}: {count} ({:.2} per op)", self.per_op(*count))?;
+        }
+
+        writeln!(f, "server messages by sender:")?;
+        for (node, count) in &self.by_node {
+            writeln!(f, "  {node}: {count} ({:.2} per op)", self.per_op(*count))?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,182 @@
+//! Recording of every line a node reads and writes, replay of recorded
+//! traces against a fresh node, and rendering of traces as diagrams.
+
+mod diagram;
+
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, BufRead, LineWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream};
+
+use crate::node::{Node, RoutedNode};
+use crate::pipe;
+use crate::runtime::Runtime;
+
+pub use diagram::sequence_diagram;
+
+/// How long after the last recorded entry replay keeps collecting output
+const REPLAY_GRACE: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    #[serde(rename = "in")]
+    Inbound,
+    #[serde(rename = "out")]
+    Outbound,
+}
+
+/// A single line read or written by a node
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceEntry {
+    /// Nanoseconds since the runtime started, from a monotonic clock
+    pub time: u64,
+    pub direction: Direction,
+    /// The line as JSON, or as a string if it was not valid JSON
+    pub message: Value,
+}
+
+/// Appends an entry to a JSONL trace for every line a runtime reads or writes.
+///
+/// Each entry is flushed as it is written so the trace survives a crash.
+#[derive(Debug, Clone)]
+pub struct Tracer {
+    start: Instant,
+    out: Arc<Mutex<LineWriter<File>>>,
+}
+
+impl Tracer {
+    /// Creates a tracer writing to the file at `path`, truncating it if it
+    /// exists
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            start: Instant::now(),
+            out: Arc::new(Mutex::new(LineWriter::new(File::create(path)?))),
+        })
+    }
+
+    pub fn record(&self, direction: Direction, line: &[u8]) {
+        let entry = TraceEntry {
+            time: self.start.elapsed().as_nanos() as u64,
+            direction,
+            message: serde_json::from_slice(line)
+                .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(line).into_owned())),
+        };
+
+        let mut bytes = serde_json::to_vec(&entry).expect("failed serializing trace entry");
+        bytes.extend(b"\n");
+        self.out
+            .lock()
+            .expect("poisoned lock")
+            .write_all(&bytes)
+            .expect("failed writing trace");
+    }
+}
+
+/// Reads a trace recorded by a [`Tracer`]
+pub fn read_trace(reader: impl BufRead) -> io::Result<Vec<TraceEntry>> {
+    reader
+        .lines()
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
+
+/// The differences between the output recorded in a trace and the output of
+/// replaying it. Messages are compared irrespective of order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplayDiff {
+    /// Recorded messages the replayed node did not write
+    pub missing: Vec<Value>,
+    /// Messages the replayed node wrote that were not recorded
+    pub unexpected: Vec<Value>,
+}
+
+impl ReplayDiff {
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty()
+    }
+}
+
+/// Feeds the inbound messages in `trace` to a fresh node of type `N`, each at
+/// the time it was originally received, and diffs the node's output against
+/// the recorded outbound messages.
+///
+/// Timers make output depend on timing, so replays are most faithful on a
+/// runtime with a paused clock.
+pub async fn replay<N>(trace: &[TraceEntry]) -> ReplayDiff
+where
+    N: for<'a> Node<'a> + 'static,
+{
+    Runtime::new().replay::<N>(trace).await
+}
+
+/// Like [`replay`], but for a [`RoutedNode`]
+pub async fn replay_routed<N>(trace: &[TraceEntry]) -> ReplayDiff
+where
+    N: RoutedNode + 'static,
+{
+    Runtime::new().replay_routed::<N>(trace).await
+}
+
+/// Replays `trace` against the node `run` runs on the given input and output
+/// streams
+pub(crate) async fn replay_with<F, Fut>(trace: &[TraceEntry], run: F) -> ReplayDiff
+where
+    F: FnOnce(DuplexStream, DuplexStream) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let (stdin, mut stdin_writer) = pipe::pipe();
+    let (stdout, stdout_reader) = pipe::pipe();
+    let start = tokio::time::Instant::now();
+    let at = |entry: &TraceEntry| start + Duration::from_nanos(entry.time);
+    let deadline = trace.iter().map(at).max().unwrap_or(start) + REPLAY_GRACE;
+
+    let feed = async move {
+        for entry in trace.iter().filter(|e| e.direction == Direction::Inbound) {
+            tokio::time::sleep_until(at(entry)).await;
+
+            let mut bytes = match &entry.message {
+                Value::String(line) => line.clone().into_bytes(),
+                message => serde_json::to_vec(message).expect("failed serializing message"),
+            };
+            bytes.extend(b"\n");
+            stdin_writer
+                .write_all(&bytes)
+                .await
+                .expect("failed writing buf");
+        }
+    };
+
+    let collect = async move {
+        let mut lines = BufReader::new(stdout_reader).lines();
+        let mut output = vec![];
+
+        while let Ok(Ok(Some(line))) = tokio::time::timeout_at(deadline, lines.next_line()).await {
+            output.push(serde_json::from_str(&line).unwrap_or(Value::String(line)));
+        }
+
+        output
+    };
+
+    let (_, _, mut unexpected) = tokio::join!(run(stdin, stdout), feed, collect);
+
+    let mut missing = vec![];
+    for recorded in trace.iter().filter(|e| e.direction == Direction::Outbound) {
+        match unexpected.iter().position(|m| *m == recorded.message) {
+            Some(index) => {
+                unexpected.remove(index);
+            }
+            None => missing.push(recorded.message.clone()),
+        }
+    }
+
+    ReplayDiff {
+        missing,
+        unexpected,
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use serde_json::Value;
+
+use super::{Direction, TraceEntry};
+use crate::message::{Message, MessageId};
+use crate::node::NodeId;
+
+/// A message observed in a trace, along with who traced it
+struct Observed {
+    /// The node whose trace the message was read from
+    node: NodeId,
+    time: u64,
+    direction: Direction,
+    message: Message<Value>,
+    /// The message as recorded, used to match sends with receives
+    key: String,
+}
+
+/// Renders the traces recorded by the nodes of a cluster as a Mermaid sequence
+/// diagram, with one lane per participant and one arrow per message.
+///
+/// Each trace is timed by its own node's clock, so the traces are merged in an
+/// order consistent with causality instead: a message is always received
+/// after it is sent. Requests that never received a reply are highlighted.
+pub fn sequence_diagram(traces: &[Vec<TraceEntry>]) -> String {
+    let observed: Vec<Vec<Observed>> = traces.iter().map(|trace| observe(trace)).collect();
+    let traced: HashSet<NodeId> = observed.iter().flatten().map(|o| o.node.clone()).collect();
+
+    let replied: HashSet<(NodeId, NodeId, MessageId)> = observed
+        .iter()
+        .flatten()
+        .filter_map(|o| {
+            let in_reply_to = o.message.body.in_reply_to?;
+            Some((o.message.dest.clone(), o.message.src.clone(), in_reply_to))
+        })
+        .collect();
+
+    let participants: BTreeSet<_> = observed
+        .iter()
+        .flatten()
+        .flat_map(|o| [&o.message.src, &o.message.dest])
+        .collect();
+    let (nodes, others): (Vec<_>, Vec<_>) = participants
+        .into_iter()
+        .partition(|participant| traced.contains(*participant));
+
+    let mut diagram = String::from("sequenceDiagram\n");
+    for participant in others.into_iter().chain(nodes) {
+        diagram.push_str(&format!("    participant {participant}\n"));
+    }
+
+    for o in merge(observed, &traced) {
+        // Messages between traced nodes are drawn when they are sent
+        if o.direction == Direction::Inbound && traced.contains(&o.message.src) {
+            continue;
+        }
+
+        let kind = o.message.type_name().unwrap_or("unknown");
+        let Message { src, dest, body } = &o.message;
+        let mut label = kind.to_string();
+        if let Some(msg_id) = body.msg_id {
+            label.push_str(&format!(" id={msg_id}"));
+        }
+
+        match (body.msg_id, body.in_reply_to) {
+            (_, Some(in_reply_to)) => {
+                diagram.push_str(&format!("    {src}-->>{dest}: {label} re={in_reply_to}\n"));
+            }
+            // Acknowledgements that identify what they acknowledge in their
+            // payload rather than by `in_reply_to`
+            _ if kind.ends_with("_ok") => {
+                diagram.push_str(&format!("    {src}-->>{dest}: {label}\n"));
+            }
+            (Some(msg_id), None) if !replied.contains(&(src.clone(), dest.clone(), msg_id)) => {
+                diagram.push_str(&format!(
+                    "    rect rgb(255, 210, 210)\n    {src}-x{dest}: {label} (no reply)\n    end\n"
+                ));
+            }
+            _ => diagram.push_str(&format!("    {src}->>{dest}: {label}\n")),
+        }
+    }
+
+    diagram
+}
+
+/// Parses the messages in `trace`, skipping lines that are not messages
+fn observe(trace: &[TraceEntry]) -> Vec<Observed> {
+    trace
+        .iter()
+        .filter_map(|entry| {
+            let message: Message<Value> = serde_json::from_value(entry.message.clone()).ok()?;
+            let node = match entry.direction {
+                Direction::Inbound => message.dest.clone(),
+                Direction::Outbound => message.src.clone(),
+            };
+
+            Some(Observed {
+                node,
+                time: entry.time,
+                direction: entry.direction,
+                message,
+                key: entry.message.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Interleaves `traces` so every message between traced nodes is received
+/// after it is sent. Otherwise messages are taken in order of the times their
+/// nodes observed them, which is only approximate across nodes.
+fn merge(traces: Vec<Vec<Observed>>, traced: &HashSet<NodeId>) -> Vec<Observed> {
+    type Cursor = std::iter::Peekable<std::vec::IntoIter<Observed>>;
+
+    let mut cursors: Vec<Cursor> = traces
+        .into_iter()
+        .map(|t| t.into_iter().peekable())
+        .collect();
+    // Messages sent but not yet received, by recorded form
+    let mut in_flight: HashMap<String, usize> = HashMap::new();
+    let mut merged = vec![];
+
+    loop {
+        let is_ready = |o: &Observed| {
+            o.direction == Direction::Outbound
+                || !traced.contains(&o.message.src)
+                || in_flight.get(&o.key).is_some_and(|count| *count > 0)
+        };
+
+        let earliest = |cursors: &mut [Cursor], ready: &dyn Fn(&Observed) -> bool| {
+            cursors
+                .iter_mut()
+                .enumerate()
+                .filter_map(|(index, cursor)| {
+                    cursor.peek().filter(|o| ready(o)).map(|o| (o.time, index))
+                })
+                .min()
+                .map(|(_, index)| index)
+        };
+
+        // A receive whose send was never traced is taken as is rather than
+        // blocking its trace forever
+        let Some(index) =
+            earliest(&mut cursors, &is_ready).or_else(|| earliest(&mut cursors, &|_| true))
+        else {
+            break;
+        };
+
+        let o = cursors[index].next().expect("peeked");
+        match o.direction {
+            Direction::Outbound => *in_flight.entry(o.key.clone()).or_default() += 1,
+            Direction::Inbound => {
+                if let Some(count) = in_flight.get_mut(&o.key) {
+                    *count = count.saturating_sub(1);
+                }
+            }
+        }
+        merged.push(o);
+    }
+
+    merged
+}
@@ -0,0 +1,6 @@
+//! Transports connecting a node's runtime to its peers and clients by means
+//! other than Maelstrom's stdin and stdout.
+
+mod tcp;
+
+pub use tcp::{TcpConfig, TcpTransport};
@@ -0,0 +1,254 @@
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::json;
+use tokio::io::{AsyncWriteExt, BufReader, DuplexStream};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use crate::codec::{self, Codec};
+use crate::node::NodeId;
+use crate::pipe;
+
+/// The sender of the `init` message the transport hands the node on startup
+const INIT_SRC: &str = "transport";
+
+/// How long accepting connections backs off after the first failure, and at
+/// most after repeated failures, such as running out of file descriptors
+const MIN_ACCEPT_BACKOFF: Duration = Duration::from_millis(10);
+const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The addresses every node in a cluster listens on
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct TcpConfig {
+    pub nodes: BTreeMap<NodeId, SocketAddr>,
+    /// The codec nodes exchange messages in. Clients always use JSON.
+    #[serde(default)]
+    pub codec: Codec,
+}
+
+impl TcpConfig {
+    /// Reads a config such as
+    /// `{"nodes": {"n0": "127.0.0.1:7000"}, "codec": "msgpack"}` from the
+    /// JSON file at `path`
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+}
+
+/// The fields needed to route a message
+#[derive(Deserialize)]
+struct Envelope {
+    src: NodeId,
+    dest: NodeId,
+}
+
+/// Where lines written by the node are sent, keyed by destination
+#[derive(Clone, Default)]
+struct Routes {
+    peers: HashMap<NodeId, UnboundedSender<Vec<u8>>>,
+    /// The connection each client last sent a message on
+    clients: Arc<Mutex<HashMap<NodeId, UnboundedSender<Vec<u8>>>>>,
+}
+
+/// Exchanges messages over TCP, in any [`Codec`].
+///
+/// The node listens on its address from the config and connects to every
+/// other node in it. Any other party can connect as a client, and receives
+/// replies on the connection it last sent from. Like Maelstrom's network,
+/// messages to a peer that cannot be reached are dropped.
+pub struct TcpTransport {
+    node_id: NodeId,
+    config: TcpConfig,
+    listener: TcpListener,
+}
+
+impl TcpTransport {
+    /// Listens on the address of `node_id` in `config`
+    pub async fn bind(config: TcpConfig, node_id: NodeId) -> io::Result<Self> {
+        let addr = *config.nodes.get(&node_id).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{node_id} is not in the config"),
+            )
+        })?;
+        let listener = TcpListener::bind(addr).await?;
+
+        Ok(Self::with_listener(config, node_id, listener))
+    }
+
+    /// Accepts connections for `node_id` on an existing `listener`
+    pub fn with_listener(config: TcpConfig, node_id: NodeId, listener: TcpListener) -> Self {
+        Self {
+            node_id,
+            config,
+            listener,
+        }
+    }
+
+    /// The codec the node should send messages to other nodes in
+    pub fn codec(&self) -> Codec {
+        self.config.codec
+    }
+
+    /// Runs the node with `run`, which is handed the pipes to use as its
+    /// input and output. The node is initialized as if by Maelstrom, with
+    /// every node in the config as its cluster.
+    ///
+    /// Frames received that cannot be routed are handed to the node as they
+    /// are, for the runtime to fail on as it would reading them from stdin.
+    ///
+    /// # Panics
+    ///
+    /// If the node writes a message that cannot be decoded
+    pub async fn serve<F, Fut>(self, run: F)
+    where
+        F: FnOnce(DuplexStream, DuplexStream) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let (node_input, input) = pipe::pipe();
+        let (node_output, output) = pipe::pipe();
+        let inbound = pipe::spawn_writer(input, None);
+
+        let init = json!({
+            "src": INIT_SRC,
+            "dest": self.node_id,
+            "body": {
+                "type": "init",
+                "msg_id": 0,
+                "node_id": self.node_id,
+                "node_ids": self.config.nodes.keys().collect::<Vec<_>>(),
+            }
+        });
+        let mut init = init.to_string().into_bytes();
+        init.push(b'\n');
+        inbound.send(init).expect("input closed");
+
+        let mut routes = Routes::default();
+        for (peer, addr) in &self.config.nodes {
+            if *peer != self.node_id {
+                let (tx, rx) = unbounded_channel();
+                tokio::spawn(send_to_peer(*addr, rx));
+                routes.peers.insert(peer.clone(), tx);
+            }
+        }
+
+        tokio::spawn(accept(self.listener, inbound, routes.clone()));
+
+        // Routing stops once the node closes its output
+        tokio::join!(run(node_input, node_output), route_output(output, routes));
+    }
+}
+
+/// Sends frames written by the node to their destinations, dropping those
+/// addressed to no one reachable
+async fn route_output(output: DuplexStream, routes: Routes) {
+    let mut output = BufReader::new(output);
+    let mut frame = Vec::new();
+
+    while codec::read_frame(&mut output, &mut frame)
+        .await
+        .expect("error reading node output")
+    {
+        let envelope: Envelope = codec::decode(&frame).expect("node wrote a malformed message");
+
+        let route = match routes.peers.get(&envelope.dest) {
+            Some(peer) => Some(peer.clone()),
+            None => routes
+                .clients
+                .lock()
+                .expect("poisoned lock")
+                .get(&envelope.dest)
+                .cloned(),
+        };
+
+        if let Some(route) = route {
+            let _ = route.send(frame.clone());
+        }
+    }
+}
+
+/// Accepts connections from peers and clients, forwarding every frame they
+/// send to the node. Failures to accept are retried with increasing backoff.
+async fn accept(listener: TcpListener, inbound: UnboundedSender<Vec<u8>>, routes: Routes) {
+    let mut backoff = MIN_ACCEPT_BACKOFF;
+
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(_) => {
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_ACCEPT_BACKOFF);
+                continue;
+            }
+        };
+        backoff = MIN_ACCEPT_BACKOFF;
+
+        let (reader, mut writer) = stream.into_split();
+        let (replies, mut replies_rx) = unbounded_channel::<Vec<u8>>();
+        let inbound = inbound.clone();
+        let routes = routes.clone();
+
+        tokio::spawn(async move {
+            while let Some(frame) = replies_rx.recv().await {
+                if writer.write_all(&frame).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        tokio::spawn(async move {
+            let mut reader = BufReader::new(reader);
+            let mut frame = Vec::new();
+
+            while let Ok(true) = codec::read_frame(&mut reader, &mut frame).await {
+                // Peers are replied to over their own connections
+                if let Ok(envelope) = codec::decode::<Envelope>(&frame) {
+                    if !routes.peers.contains_key(&envelope.src) {
+                        routes
+                            .clients
+                            .lock()
+                            .expect("poisoned lock")
+                            .insert(envelope.src, replies.clone());
+                    }
+                }
+
+                if inbound.send(frame.clone()).is_err() {
+                    break;
+                }
+            }
+
+            // Replies to clients last seen on this connection are dropped,
+            // like those to clients never seen at all
+            routes
+                .clients
+                .lock()
+                .expect("poisoned lock")
+                .retain(|_, route| !route.same_channel(&replies));
+        });
+    }
+}
+
+/// Writes frames to the peer at `addr`, connecting whenever there is no
+/// connection
+async fn send_to_peer(addr: SocketAddr, mut rx: UnboundedReceiver<Vec<u8>>) {
+    let mut stream: Option<TcpStream> = None;
+
+    while let Some(frame) = rx.recv().await {
+        if stream.is_none() {
+            stream = TcpStream::connect(addr).await.ok();
+        }
+
+        if let Some(connection) = &mut stream {
+            if connection.write_all(&frame).await.is_err() {
+                stream = None;
+            }
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Writing of queued messages as frames of their codec.
+
+use std::collections::HashSet;
+
+use serde::Serialize;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::codec::Codec;
+use crate::message::Message;
+use crate::middleware::Outbound;
+use crate::node::NodeId;
+use crate::outbox::Receiver;
+use crate::trace::{Direction, Tracer};
+
+/// The most bytes of queued messages coalesced into a single write
+const MAX_BATCH_BYTES: usize = 64 * 1024;
+
+/// Writes messages to an underlying writer, coalescing every message queued
+/// at the time of a write into that write and flushing whenever the queue
+/// runs dry
+pub struct MessageWriter<W> {
+    writer: W,
+    /// Reused for every batch
+    buf: Vec<u8>,
+    /// The codec of messages to other nodes
+    codec: Codec,
+    /// The nodes of the cluster, as opposed to clients and services
+    servers: HashSet<NodeId>,
+    tracer: Option<Tracer>,
+    /// Where frames go instead of the writer, if there is middleware
+    layers: Option<Outbound>,
+}
+
+impl<W: AsyncWrite + Unpin> MessageWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            buf: Vec::with_capacity(MAX_BATCH_BYTES),
+            codec: Codec::Json,
+            servers: HashSet::new(),
+            tracer: None,
+            layers: None,
+        }
+    }
+
+    /// Encodes messages to other nodes with `codec`. Messages to clients are
+    /// always JSON.
+    pub fn codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Names the nodes of the cluster, which messages are encoded with the
+    /// writer's codec to. There are none by default.
+    pub fn servers(mut self, servers: impl IntoIterator<Item = NodeId>) -> Self {
+        self.servers = servers.into_iter().collect();
+        self
+    }
+
+    /// Records every message written to `tracer`
+    pub fn trace(mut self, tracer: Option<Tracer>) -> Self {
+        self.tracer = tracer;
+        self
+    }
+
+    /// Hands every frame to the middleware instead of writing it, leaving
+    /// the writer unused
+    pub(crate) fn layers(mut self, outbound: Outbound) -> Self {
+        self.layers = Some(outbound);
+        self
+    }
+
+    /// Writes messages as they are queued on `rx`, until all of its senders
+    /// have been dropped
+    pub async fn drain<P: Serialize>(&mut self, rx: &mut Receiver<P>) {
+        while let Some(message) = rx.recv().await {
+            self.buf.clear();
+            self.encode(&message);
+            self.fill(rx);
+            self.write(rx.is_empty()).await;
+        }
+    }
+
+    /// Like [`MessageWriter::drain`], but writes messages as they are queued
+    /// on either `control` or `rx`, preferring `control`, until all senders
+    /// of both have been dropped
+    pub async fn drain_with<C, P>(&mut self, control: &mut Receiver<C>, rx: &mut Receiver<P>)
+    where
+        C: Serialize,
+        P: Serialize,
+    {
+        let (mut control_open, mut rx_open) = (true, true);
+
+        while control_open || rx_open {
+            self.buf.clear();
+
+            tokio::select! {
+                biased;
+                message = control.recv(), if control_open => match message {
+                    Some(message) => self.encode(&message),
+                    None => control_open = false,
+                },
+                message = rx.recv(), if rx_open => match message {
+                    Some(message) => self.encode(&message),
+                    None => rx_open = false,
+                },
+            }
+
+            self.fill(control);
+            self.fill(rx);
+            if !self.buf.is_empty() {
+                self.write(control.is_empty() && rx.is_empty()).await;
+            }
+        }
+    }
+
+    /// Writes `message` on its own and flushes the writer
+    pub async fn write_message<P: Serialize>(&mut self, message: &Message<P>) {
+        self.buf.clear();
+        self.encode(message);
+        self.write(true).await;
+    }
+
+    /// Appends messages already queued on `rx` to the current batch, until it
+    /// is full
+    fn fill<P: Serialize>(&mut self, rx: &mut Receiver<P>) {
+        while self.buf.len() < MAX_BATCH_BYTES {
+            match rx.try_recv() {
+                Some(message) => self.encode(&message),
+                None => break,
+            }
+        }
+    }
+
+    /// Writes the current batch, flushing the writer if `flush` is set
+    async fn write(&mut self, flush: bool) {
+        self.writer
+            .write_all(&self.buf)
+            .await
+            .expect("failed writing buf");
+
+        if flush {
+            self.writer.flush().await.expect("failed flushing writer");
+        }
+    }
+
+    /// Appends `message` to the current batch
+    fn encode<P: Serialize>(&mut self, message: &Message<P>) {
+        let codec = match self.servers.contains(&message.dest) {
+            true => self.codec,
+            false => Codec::Json,
+        };
+        let start = self.buf.len();
+        codec.encode(message, &mut self.buf);
+
+        if let Some(outbound) = &self.layers {
+            return outbound.send(self.buf.split_off(start));
+        }
+
+        if let Some(tracer) = &self.tracer {
+            match codec {
+                Codec::Json => {
+                    tracer.record(Direction::Outbound, self.buf[start..].trim_ascii_end())
+                }
+                _ => tracer.record(
+                    Direction::Outbound,
+                    &serde_json::to_vec(message).expect("failed serializing message"),
+                ),
+            }
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use common::codec::{self, Codec};
+use common::message::{Message, MessageBody, MessagePayload};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::io::BufReader;
+
+const CODECS: [Codec; 3] = [Codec::Json, Codec::MessagePack, Codec::Cbor];
+
+/// One of every payload, which fails to compile when a variant is added
+/// until it is covered here too
+fn payloads() -> Vec<MessagePayload> {
+    let payloads = vec![
+        MessagePayload::Init {
+            node_id: "n0".to_string(),
+            node_ids: vec!["n0".to_string(), "n1".to_string()],
+        },
+        MessagePayload::InitOk,
+        MessagePayload::Echo {
+            echo: "hello \"world\"".to_string(),
+        },
+        MessagePayload::Generate,
+        MessagePayload::GenerateOk {
+            id: "n0-1".to_string(),
+        },
+        MessagePayload::EchoOk {
+            echo: "hello".to_string(),
+        },
+        MessagePayload::Broadcast { message: 1000 },
+        MessagePayload::BroadcastOk,
+        MessagePayload::Read,
+        MessagePayload::ReadOk {
+            messages: vec![1, -2, 3, i32::MAX],
+        },
+        MessagePayload::Topology {
+            topology: HashMap::from([
+                ("n0".to_string(), vec!["n1".to_string()]),
+                ("n1".to_string(), vec![]),
+            ]),
+        },
+        MessagePayload::TopologyOk,
+        MessagePayload::Add { delta: u32::MAX },
+        MessagePayload::AddOk,
+    ];
+
+    for payload in &payloads {
+        match payload {
+            MessagePayload::Init { .. }
+            | MessagePayload::InitOk
+            | MessagePayload::Echo { .. }
+            | MessagePayload::Generate
+            | MessagePayload::GenerateOk { .. }
+            | MessagePayload::EchoOk { .. }
+            | MessagePayload::Broadcast { .. }
+            | MessagePayload::BroadcastOk
+            | MessagePayload::Read
+            | MessagePayload::ReadOk { .. }
+            | MessagePayload::Topology { .. }
+            | MessagePayload::TopologyOk
+            | MessagePayload::Add { .. }
+            | MessagePayload::AddOk => {}
+        }
+    }
+
+    payloads
+}
+
+fn message(n: u64, payload: MessagePayload) -> Message<MessagePayload> {
+    Message {
+        src: "n0".to_string(),
+        dest: "n1".to_string(),
+        body: MessageBody {
+            msg_id: Some(n),
+            in_reply_to: n.checked_sub(1),
+            payload,
+        },
+    }
+}
+
+#[tokio::test]
+async fn every_payload_round_trips_through_every_codec() {
+    for codec in CODECS {
+        for (n, payload) in payloads().into_iter().enumerate() {
+            let message = message(n as u64, payload);
+            let mut buf = vec![];
+            codec.encode(&message, &mut buf);
+            assert_eq!(Codec::of(&buf), codec);
+
+            let mut frame = vec![];
+            let mut reader = BufReader::new(buf.as_slice());
+            assert!(codec::read_frame(&mut reader, &mut frame).await.unwrap());
+            assert_eq!(frame, buf, "{codec:?} frame was not read whole");
+
+            let decoded: Message<MessagePayload> = codec::decode(&frame).unwrap();
+            assert_eq!(
+                serde_json::to_value(&decoded).unwrap(),
+                serde_json::to_value(&message).unwrap(),
+                "{codec:?} did not round trip",
+            );
+            assert_eq!(
+                serde_json::from_slice::<Value>(&codec::to_json(&frame)).unwrap(),
+                serde_json::to_value(&message).unwrap(),
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn frames_of_every_codec_can_share_a_stream() {
+    let mut stream = vec![];
+    for (n, codec) in CODECS.iter().cycle().take(9).enumerate() {
+        let payload = MessagePayload::Echo {
+            echo: format!("echo {n}"),
+        };
+        codec.encode(&message(n as u64, payload), &mut stream);
+    }
+
+    let mut reader = BufReader::new(stream.as_slice());
+    let mut frame = vec![];
+    let mut echoes = vec![];
+    while codec::read_frame(&mut reader, &mut frame).await.unwrap() {
+        let message: Message<MessagePayload> = codec::decode(&frame).unwrap();
+        let MessagePayload::Echo { echo } = message.body.payload else {
+            panic!("unexpected payload");
+        };
+        echoes.push(echo);
+    }
+
+    assert_eq!(
+        echoes,
+        (0..9).map(|n| format!("echo {n}")).collect::<Vec<_>>()
+    );
+}
+
+#[derive(Deserialize)]
+struct Borrowed<'a> {
+    echo: &'a str,
+}
+
+#[test]
+fn binary_payloads_can_borrow_from_the_frame() {
+    for codec in [Codec::MessagePack, Codec::Cbor] {
+        let message = message(
+            1,
+            MessagePayload::Echo {
+                echo: "borrowed".to_string(),
+            },
+        );
+        let mut frame = vec![];
+        codec.encode(&message, &mut frame);
+
+        let decoded: Message<Borrowed> = codec::decode(&frame).unwrap();
+        assert_eq!(decoded.body.payload.echo, "borrowed");
+    }
+}
+
+#[tokio::test]
+async fn frames_longer_than_the_maximum_are_rejected() {
+    let mut header = vec![0x01];
+    header.extend(u32::MAX.to_be_bytes());
+    let line = vec![b'x'; codec::MAX_FRAME_LEN + 1];
+
+    for stream in [header, line] {
+        let mut reader = BufReader::new(stream.as_slice());
+        let mut frame = vec![];
+        let error = codec::read_frame(&mut reader, &mut frame)
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(frame.len() <= codec::MAX_FRAME_LEN + 1);
+    }
+}
@@ -0,0 +1,205 @@
+//! Property tests checking that every CRDT's merge forms a join-semilattice.
+//!
+//! Rather than generating arbitrary states, which may not be reachable (e.g.
+//! two replicas issuing the same `Dot`), states are produced by applying random
+//! operations and merges to a small cluster of replicas.
+
+use std::fmt::Debug;
+
+use common::crdt::{Crdt, GCounter, GSet, LwwRegister, MvRegister, OrSet, PnCounter, TwoPhaseSet};
+use common::node::NodeId;
+use proptest::prelude::*;
+
+const REPLICAS: usize = 3;
+
+trait Model: Crdt + Default + PartialEq + Debug {
+    type Op: Debug + Clone;
+
+    fn op() -> BoxedStrategy<Self::Op>;
+    fn apply(&mut self, node: &NodeId, op: &Self::Op);
+}
+
+#[derive(Debug, Clone)]
+enum Step<Op> {
+    Apply { replica: usize, op: Op },
+    Merge { from: usize, to: usize },
+}
+
+fn steps<C: Model>() -> impl Strategy<Value = Vec<Step<C::Op>>> {
+    let step = prop_oneof![
+        (0..REPLICAS, C::op()).prop_map(|(replica, op)| Step::Apply { replica, op }),
+        (0..REPLICAS, 0..REPLICAS).prop_map(|(from, to)| Step::Merge { from, to }),
+    ];
+
+    prop::collection::vec(step, 0..48)
+}
+
+fn replicas<C: Model>(steps: &[Step<C::Op>]) -> Vec<C> {
+    let mut replicas: Vec<C> = (0..REPLICAS).map(|_| C::default()).collect();
+
+    for step in steps {
+        match step {
+            Step::Apply { replica, op } => {
+                replicas[*replica].apply(&format!("n{replica}"), op);
+            }
+            Step::Merge { from, to } => {
+                let from = replicas[*from].clone();
+                replicas[*to].merge(&from);
+            }
+        }
+    }
+
+    replicas
+}
+
+fn merged<C: Crdt>(a: &C, b: &C) -> C {
+    let mut merged = a.clone();
+    merged.merge(b);
+    merged
+}
+
+impl Model for GCounter {
+    type Op = u64;
+
+    fn op() -> BoxedStrategy<Self::Op> {
+        (0..100u64).boxed()
+    }
+
+    fn apply(&mut self, node: &NodeId, delta: &Self::Op) {
+        self.increment(node, *delta);
+    }
+}
+
+impl Model for PnCounter {
+    type Op = (bool, u64);
+
+    fn op() -> BoxedStrategy<Self::Op> {
+        (any::<bool>(), 0..100u64).boxed()
+    }
+
+    fn apply(&mut self, node: &NodeId, (increment, delta): &Self::Op) {
+        if *increment {
+            self.increment(node, *delta);
+        } else {
+            self.decrement(node, *delta);
+        }
+    }
+}
+
+impl Model for GSet<u8> {
+    type Op = u8;
+
+    fn op() -> BoxedStrategy<Self::Op> {
+        (0..8u8).boxed()
+    }
+
+    fn apply(&mut self, _node: &NodeId, element: &Self::Op) {
+        self.insert(*element);
+    }
+}
+
+impl Model for TwoPhaseSet<u8> {
+    type Op = (bool, u8);
+
+    fn op() -> BoxedStrategy<Self::Op> {
+        (any::<bool>(), 0..8u8).boxed()
+    }
+
+    fn apply(&mut self, _node: &NodeId, (insert, element): &Self::Op) {
+        if *insert {
+            self.insert(*element);
+        } else {
+            self.remove(element);
+        }
+    }
+}
+
+impl Model for OrSet<u8> {
+    type Op = (bool, u8);
+
+    fn op() -> BoxedStrategy<Self::Op> {
+        (any::<bool>(), 0..8u8).boxed()
+    }
+
+    fn apply(&mut self, node: &NodeId, (insert, element): &Self::Op) {
+        if *insert {
+            self.insert(node, *element);
+        } else {
+            self.remove(element);
+        }
+    }
+}
+
+impl Model for LwwRegister<u8> {
+    type Op = (u64, u8);
+
+    fn op() -> BoxedStrategy<Self::Op> {
+        (0..16u64, any::<u8>()).boxed()
+    }
+
+    fn apply(&mut self, node: &NodeId, (timestamp, value): &Self::Op) {
+        self.set(node, *timestamp, *value);
+    }
+}
+
+impl Model for MvRegister<u8> {
+    type Op = u8;
+
+    fn op() -> BoxedStrategy<Self::Op> {
+        any::<u8>().boxed()
+    }
+
+    fn apply(&mut self, node: &NodeId, value: &Self::Op) {
+        self.set(node, *value);
+    }
+}
+
+macro_rules! semilattice_tests {
+    ($name:ident, $crdt:ty) => {
+        mod $name {
+            use super::*;
+
+            proptest! {
+                #[test]
+                fn merge_is_commutative(steps in steps::<$crdt>()) {
+                    let replicas = replicas::<$crdt>(&steps);
+                    let (a, b) = (&replicas[0], &replicas[1]);
+
+                    prop_assert_eq!(merged(a, b), merged(b, a));
+                }
+
+                #[test]
+                fn merge_is_associative(steps in steps::<$crdt>()) {
+                    let replicas = replicas::<$crdt>(&steps);
+                    let (a, b, c) = (&replicas[0], &replicas[1], &replicas[2]);
+
+                    prop_assert_eq!(merged(&merged(a, b), c), merged(a, &merged(b, c)));
+                }
+
+                #[test]
+                fn merge_is_idempotent(steps in steps::<$crdt>()) {
+                    let replicas = replicas::<$crdt>(&steps);
+                    let a = &replicas[0];
+
+                    prop_assert_eq!(&merged(a, a), a);
+                }
+
+                #[test]
+                fn serde_round_trips(steps in steps::<$crdt>()) {
+                    let replicas = replicas::<$crdt>(&steps);
+                    let json = serde_json::to_string(&replicas[0]).unwrap();
+
+                    prop_assert_eq!(&serde_json::from_str::<$crdt>(&json).unwrap(), &replicas[0]);
+                }
+            }
+        }
+    };
+}
+
+semilattice_tests!(gcounter, GCounter);
+semilattice_tests!(pncounter, PnCounter);
+semilattice_tests!(gset, GSet<u8>);
+semilattice_tests!(two_phase_set, TwoPhaseSet<u8>);
+semilattice_tests!(or_set, OrSet<u8>);
+semilattice_tests!(lww_register, LwwRegister<u8>);
+semilattice_tests!(mv_register, MvRegister<u8>);
@@ -0,0 +1,66 @@
+use common::crdt::{Crdt, DeltaReplicator, GCounter};
+
+fn increment(replicator: &mut DeltaReplicator<GCounter>, node: &str, delta: u64) {
+    let node = node.to_string();
+    let mut counter = GCounter::new();
+    counter.increment(&node, replicator.state().get(&node) + delta);
+    replicator.apply(counter);
+}
+
+#[test]
+fn ships_only_unacknowledged_deltas() {
+    let mut a = DeltaReplicator::<GCounter>::new(["b".to_string()], 8);
+    let mut b = DeltaReplicator::<GCounter>::new(["a".to_string()], 8);
+
+    increment(&mut a, "a", 1);
+    increment(&mut a, "a", 2);
+
+    let sync = a.sync_for(&"b".to_string()).unwrap();
+    assert!(!sync.full);
+    let seq = b.receive(&"a".to_string(), sync);
+    a.ack(&"b".to_string(), seq);
+
+    assert_eq!(b.state().value(), 3);
+    assert!(a.sync_for(&"b".to_string()).is_none());
+
+    increment(&mut a, "a", 4);
+
+    let sync = a.sync_for(&"b".to_string()).unwrap();
+    assert_eq!(sync.state.value(), 7);
+    b.receive(&"a".to_string(), sync);
+
+    assert_eq!(b.state().value(), 7);
+}
+
+#[test]
+fn falls_back_to_full_state_for_lagging_peers() {
+    let mut a = DeltaReplicator::<GCounter>::new(["b".to_string()], 2);
+
+    increment(&mut a, "a", 1);
+    increment(&mut a, "c", 1);
+    increment(&mut a, "d", 1);
+
+    let sync = a.sync_for(&"b".to_string()).unwrap();
+    assert!(sync.full);
+    assert_eq!(&sync.state, a.state());
+}
+
+#[test]
+fn deltas_are_not_sent_back_to_their_origin() {
+    let (a, b, c) = ("a".to_string(), "b".to_string(), "c".to_string());
+    let mut from = DeltaReplicator::<GCounter>::new([b.clone()], 8);
+    let mut to = DeltaReplicator::<GCounter>::new([a.clone(), c.clone()], 8);
+
+    increment(&mut from, "a", 1);
+    to.receive(&a, from.sync_for(&b).unwrap());
+
+    assert!(to.sync_for(&a).is_none());
+    assert_eq!(to.sync_for(&c).unwrap().state.value(), 1);
+
+    // Once the delta is relayed to every other peer it is no longer buffered
+    let seq = to.sync_for(&c).unwrap().seq;
+    to.ack(&c, seq);
+    increment(&mut to, "b", 2);
+
+    assert_eq!(to.sync_for(&a).unwrap().state.value(), 2);
+}
@@ -0,0 +1,269 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::message::{MaelstromPayload, Message, MessageId};
+use common::middleware::{Action, Dedup, Envelope, Layer};
+use common::node::{Node, NodeId};
+use common::outbox::Outbox;
+use common::runtime::Runtime;
+use common::trace::{self, Direction, Tracer};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+mod support;
+use support::Sender;
+
+#[derive(Debug, MaelstromPayload)]
+enum EchoPayload {
+    Echo { echo: String },
+    EchoOk { echo: String, handled: u64 },
+}
+
+struct EchoNode {
+    sender: Sender<EchoPayload>,
+    /// How many echoes the node has handled
+    handled: u64,
+}
+
+impl Node<'_> for EchoNode {
+    type Payload = EchoPayload;
+    type Output = EchoPayload;
+
+    fn handle_message(&mut self, message: Message<Self::Payload>) {
+        let EchoPayload::Echo { echo } = message.body.payload else {
+            return;
+        };
+
+        self.handled += 1;
+        self.sender.send(
+            message.src,
+            message.body.msg_id,
+            EchoPayload::EchoOk {
+                echo,
+                handled: self.handled,
+            },
+        );
+    }
+
+    fn from_init(node_id: NodeId, _neighbors: Vec<NodeId>, tx: Outbox<Self::Output>) -> Self {
+        Self {
+            sender: Sender::new(node_id, tx),
+            handled: 0,
+        }
+    }
+
+    fn next_msg_id(&mut self) -> MessageId {
+        self.sender.next_msg_id()
+    }
+}
+
+/// Records the type of every message in both directions
+#[derive(Clone, Default)]
+struct Log(Arc<Mutex<Vec<String>>>);
+
+impl Log {
+    fn record(&self, direction: &str, envelope: &mut Envelope) {
+        let kind = envelope.message().body.payload["type"]
+            .as_str()
+            .unwrap_or_default();
+        self.0
+            .lock()
+            .expect("poisoned lock")
+            .push(format!("{direction} {kind}"));
+    }
+}
+
+impl Layer for Log {
+    fn inbound(&self, mut envelope: Envelope) -> Action {
+        self.record("in", &mut envelope);
+        Action::Pass(envelope)
+    }
+
+    fn outbound(&self, mut envelope: Envelope) -> Action {
+        self.record("out", &mut envelope);
+        Action::Pass(envelope)
+    }
+}
+
+/// Drops echoes of "drop", delays echoes of "slow" and shouts the rest
+struct Faults;
+
+impl Layer for Faults {
+    fn inbound(&self, mut envelope: Envelope) -> Action {
+        match envelope.message().body.payload["echo"].as_str() {
+            Some("drop") => Action::Drop,
+            Some("slow") => Action::Delay(envelope, Duration::from_millis(50)),
+            Some(echo) => {
+                let echo = echo.to_uppercase();
+                envelope.message_mut().body.payload["echo"] = json!(echo);
+                Action::Pass(envelope)
+            }
+            None => Action::Pass(envelope),
+        }
+    }
+
+    fn outbound(&self, mut envelope: Envelope) -> Action {
+        envelope.message_mut().body.payload["layer"] = json!("faults");
+        Action::Pass(envelope)
+    }
+}
+
+#[tokio::test]
+async fn layers_can_modify_drop_and_delay_messages() {
+    let (stdin, mut stdin_writer) = tokio::io::duplex(1024);
+    let (stdout, stdout_reader) = tokio::io::duplex(1024);
+    let log = Log::default();
+    let runtime = Runtime::new().layer(log.clone()).layer(Faults);
+    tokio::spawn(runtime.run::<EchoNode, _, _>(stdin, stdout));
+
+    let mut input = json!({
+        "src": "c0", "dest": "n0",
+        "body": { "type": "init", "msg_id": 1, "node_id": "n0", "node_ids": ["n0"] }
+    })
+    .to_string();
+    for (msg_id, echo) in [(2, "slow"), (3, "drop"), (4, "fast")] {
+        let line = json!({ "src": "c1", "dest": "n0", "body": { "type": "echo", "msg_id": msg_id, "echo": echo } });
+        input.push_str(&format!("\n{line}"));
+    }
+    input.push('\n');
+    stdin_writer.write_all(input.as_bytes()).await.unwrap();
+
+    let mut replies = BufReader::new(stdout_reader).lines();
+    let mut bodies = vec![];
+    for _ in 0..3 {
+        let line = replies.next_line().await.unwrap().unwrap();
+        bodies.push(serde_json::from_str::<Value>(&line).unwrap()["body"].clone());
+    }
+
+    assert_eq!(bodies[0]["type"], "init_ok");
+    // The slow echo is overtaken, and the dropped one never answered
+    assert_eq!(bodies[1]["in_reply_to"], 4);
+    assert_eq!(bodies[1]["echo"], "FAST");
+    assert_eq!(bodies[2]["in_reply_to"], 2);
+    assert_eq!(bodies[2]["echo"], "slow");
+    assert!(bodies.iter().all(|body| body["layer"] == "faults"));
+
+    // Directions are handled by separate tasks, so are only ordered within
+    // themselves
+    let log = log.0.lock().unwrap();
+    let (inbound, outbound): (Vec<_>, Vec<_>) =
+        log.iter().partition(|entry| entry.starts_with("in"));
+    assert_eq!(inbound, ["in init", "in echo", "in echo", "in echo"]);
+    assert_eq!(outbound, ["out init_ok", "out echo_ok", "out echo_ok"]);
+}
+
+#[tokio::test]
+async fn repeated_requests_are_answered_with_the_cached_reply() {
+    let (stdin, mut stdin_writer) = tokio::io::duplex(1024);
+    let (stdout, stdout_reader) = tokio::io::duplex(1024);
+    let runtime = Runtime::new().layer(Dedup::new(2));
+    tokio::spawn(runtime.run::<EchoNode, _, _>(stdin, stdout));
+
+    let init = json!({
+        "src": "c0", "dest": "n0",
+        "body": { "type": "init", "msg_id": 1, "node_id": "n0", "node_ids": ["n0"] }
+    });
+    let echo = |src: &str, msg_id: u64| json!({ "src": src, "dest": "n0", "body": { "type": "echo", "msg_id": msg_id, "echo": "hi" } });
+    let lines = [
+        init,
+        echo("c1", 1),
+        // Repeats are answered without the node seeing them
+        echo("c1", 1),
+        // Messages are told apart by sender as well as ID
+        echo("c2", 1),
+        echo("c2", 2),
+        // Only the last two messages are remembered
+        echo("c1", 1),
+    ];
+
+    let mut replies = BufReader::new(stdout_reader).lines();
+    let mut bodies = vec![];
+    for line in &lines {
+        stdin_writer
+            .write_all(format!("{line}\n").as_bytes())
+            .await
+            .unwrap();
+
+        let reply = replies.next_line().await.unwrap().unwrap();
+        bodies.push(serde_json::from_str::<Value>(&reply).unwrap()["body"].clone());
+    }
+
+    assert_eq!(bodies[0]["type"], "init_ok");
+    assert_eq!(bodies[1]["handled"], 1);
+    assert_eq!(bodies[2], bodies[1]);
+    assert_eq!(bodies[3]["handled"], 2);
+    assert_eq!(bodies[4]["handled"], 3);
+    assert_eq!(bodies[5]["handled"], 4);
+    assert_eq!(bodies[5]["in_reply_to"], 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn traces_are_recorded_outside_the_layers_and_replayed_behind_them() {
+    let path = std::env::temp_dir().join(format!("middleware-trace-{}.jsonl", std::process::id()));
+    let (stdin, mut stdin_writer) = tokio::io::duplex(1024);
+    let (stdout, stdout_reader) = tokio::io::duplex(1024);
+    let runtime = Runtime::new()
+        .trace(Tracer::create(&path).unwrap())
+        .layer(Dedup::new(8));
+    tokio::spawn(runtime.run::<EchoNode, _, _>(stdin, stdout));
+
+    let init = json!({
+        "src": "c0", "dest": "n0",
+        "body": { "type": "init", "msg_id": 1, "node_id": "n0", "node_ids": ["n0"] }
+    });
+    let echo =
+        json!({ "src": "c1", "dest": "n0", "body": { "type": "echo", "msg_id": 1, "echo": "hi" } });
+    let mut replies = BufReader::new(stdout_reader).lines();
+    for line in [&init, &echo, &echo] {
+        stdin_writer
+            .write_all(format!("{line}\n").as_bytes())
+            .await
+            .unwrap();
+        replies.next_line().await.unwrap().unwrap();
+    }
+
+    let file = std::fs::File::open(&path).unwrap();
+    let mut entries = trace::read_trace(std::io::BufReader::new(file)).unwrap();
+    std::fs::remove_file(path).unwrap();
+
+    // The repeat the node never saw, and the reply it never wrote again
+    let directions: Vec<_> = entries.iter().map(|e| e.direction).collect();
+    assert_eq!(
+        directions,
+        [Direction::Inbound, Direction::Outbound].repeat(3)
+    );
+    assert_eq!(entries[4].message, echo);
+    assert_eq!(entries[5].message, entries[3].message);
+
+    // The messages were recorded microseconds apart, too close for the timer
+    // to replay them one at a time
+    for (n, entry) in entries.iter_mut().enumerate() {
+        entry.time = n as u64 * 10_000_000;
+    }
+    let diff = Runtime::new()
+        .layer(Dedup::new(8))
+        .replay::<EchoNode>(&entries)
+        .await;
+    assert!(diff.is_empty(), "{diff:?}");
+}
+
+#[tokio::test]
+async fn frames_the_layers_cannot_decode_stop_the_node() {
+    let (stdin, mut stdin_writer) = tokio::io::duplex(1024);
+    let (stdout, stdout_reader) = tokio::io::duplex(1024);
+    let runtime = Runtime::new().layer(Dedup::new(8));
+    let node = tokio::spawn(runtime.run::<EchoNode, _, _>(stdin, stdout));
+
+    let init = json!({
+        "src": "c0", "dest": "n0",
+        "body": { "type": "init", "msg_id": 1, "node_id": "n0", "node_ids": ["n0"] }
+    });
+    stdin_writer
+        .write_all(format!("{init}\nnot a message\n").as_bytes())
+        .await
+        .unwrap();
+
+    let mut replies = BufReader::new(stdout_reader).lines();
+    replies.next_line().await.unwrap().unwrap();
+    assert!(node.await.unwrap_err().is_panic());
+}
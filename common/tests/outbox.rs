@@ -0,0 +1,126 @@
+use std::time::Duration;
+
+use common::message::{Message, MessageBody};
+use common::outbox::{self, Metrics, Overflow, SendError};
+
+fn message(n: u64) -> Message<u64> {
+    Message {
+        src: "n0".to_string(),
+        dest: "n1".to_string(),
+        body: MessageBody {
+            msg_id: Some(n),
+            in_reply_to: None,
+            payload: n,
+        },
+    }
+}
+
+async fn drain(rx: &mut outbox::Receiver<u64>) -> Vec<u64> {
+    let mut received = vec![];
+    while let Some(message) = rx.recv().await {
+        received.push(message.body.payload);
+    }
+    received
+}
+
+#[tokio::test]
+async fn drop_oldest_keeps_the_newest_messages() {
+    let (tx, mut rx) = outbox::channel(2, Overflow::DropOldest);
+
+    for n in 0..5 {
+        tx.send(message(n)).unwrap();
+    }
+    let metrics = tx.metrics();
+    drop(tx);
+
+    assert_eq!(drain(&mut rx).await, vec![3, 4]);
+    assert_eq!(
+        metrics,
+        Metrics {
+            depth: 2,
+            max_depth: 2,
+            dropped: 3,
+            rejected: 0
+        }
+    );
+}
+
+#[tokio::test]
+async fn reject_returns_the_message_to_the_sender() {
+    let (tx, mut rx) = outbox::channel(2, Overflow::Reject);
+
+    tx.send(message(0)).unwrap();
+    tx.send(message(1)).unwrap();
+    let rejected = tx.send(message(2)).unwrap_err();
+
+    assert!(matches!(rejected, SendError::Full(_)));
+    assert_eq!(rejected.into_message().body.payload, 2);
+    assert_eq!(tx.metrics().rejected, 1);
+
+    rx.recv().await.unwrap();
+    tx.send(message(3)).unwrap();
+    drop(tx);
+    assert_eq!(drain(&mut rx).await, vec![1, 3]);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn block_waits_for_the_writer() {
+    let (tx, mut rx) = outbox::channel(1, Overflow::Block);
+
+    let sender = tokio::spawn(async move {
+        for n in 0..10 {
+            tx.send(message(n)).unwrap();
+        }
+        tx.metrics()
+    });
+
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert_eq!(drain(&mut rx).await, (0..10).collect::<Vec<_>>());
+    assert_eq!(sender.await.unwrap().max_depth, 1);
+}
+
+#[tokio::test]
+async fn block_reports_full_on_a_current_thread_runtime() {
+    let (tx, _rx) = outbox::channel(1, Overflow::Block);
+
+    tx.send(message(0)).unwrap();
+
+    assert!(matches!(tx.send(message(1)), Err(SendError::Full(_))));
+}
+
+#[tokio::test]
+async fn sending_after_the_receiver_is_dropped_fails() {
+    let (tx, rx) = outbox::channel(1, Overflow::Block);
+    drop(rx);
+
+    assert!(matches!(tx.send(message(0)), Err(SendError::Closed(_))));
+    assert!(matches!(tx.send(message(1)), Err(SendError::Closed(_))));
+}
+
+#[tokio::test]
+async fn receiver_waits_for_every_sender() {
+    let (tx, mut rx) = outbox::channel(4, Overflow::Reject);
+    let other = tx.clone();
+
+    drop(tx);
+    other.send(message(7)).unwrap();
+    drop(other);
+
+    assert_eq!(drain(&mut rx).await, vec![7]);
+}
+
+#[tokio::test]
+async fn send_or_drop_sheds_messages_a_full_outbox_rejects() {
+    let (tx, mut rx) = outbox::channel(1, Overflow::Reject);
+    let metrics = rx.metrics_handle();
+
+    tx.send_or_drop(message(0));
+    tx.send_or_drop(message(1));
+    drop(tx);
+
+    assert_eq!(drain(&mut rx).await, vec![0]);
+    assert_eq!(metrics.get().unwrap().rejected, 1);
+
+    drop(rx);
+    assert_eq!(metrics.get(), None);
+}
@@ -0,0 +1,163 @@
+use std::borrow::Cow;
+
+use common::message::{MaelstromPayload, Message, Payload};
+use serde_json::json;
+
+#[derive(Debug, Clone, PartialEq, MaelstromPayload)]
+enum GossipPayload {
+    Gossip {
+        message: u64,
+    },
+    GossipOk,
+    /// Renamed variants keep their name in `type`
+    #[serde(rename = "ihave")]
+    IHave {
+        messages: Vec<u64>,
+    },
+    Read {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        key: Option<String>,
+    },
+    ReadOk {
+        value: u64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, MaelstromPayload)]
+#[serde(rename_all = "kebab-case")]
+enum KebabPayload {
+    Txn {
+        ops: Vec<u64>,
+    },
+    TxnOk,
+    /// Renamed for serializing only, so read back under its own name
+    #[serde(rename(serialize = "heart-beat", deserialize = "heartbeat"))]
+    HeartBeat,
+}
+
+#[derive(Debug, Clone, PartialEq, MaelstromPayload)]
+#[serde(rename_all = "PascalCase")]
+enum PascalPayload {
+    Lookup { key: String },
+    LookupOk,
+}
+
+#[derive(Debug, MaelstromPayload)]
+enum Borrowing<'a> {
+    Echo {
+        #[serde(borrow)]
+        echo: Cow<'a, str>,
+    },
+    EchoOk {
+        echo: String,
+    },
+}
+
+#[test]
+fn payloads_are_tagged_by_their_type_name() {
+    let payloads = [
+        (
+            GossipPayload::Gossip { message: 3 },
+            json!({ "type": "gossip", "message": 3 }),
+        ),
+        (GossipPayload::GossipOk, json!({ "type": "gossip_ok" })),
+        (
+            GossipPayload::IHave {
+                messages: vec![1, 2],
+            },
+            json!({ "type": "ihave", "messages": [1, 2] }),
+        ),
+        (GossipPayload::Read { key: None }, json!({ "type": "read" })),
+        (
+            GossipPayload::ReadOk { value: 7 },
+            json!({ "type": "read_ok", "value": 7 }),
+        ),
+    ];
+
+    for (payload, expected) in payloads {
+        assert_eq!(serde_json::to_value(&payload).unwrap(), expected);
+        assert_eq!(expected["type"], payload.type_name());
+        assert_eq!(
+            serde_json::from_value::<GossipPayload>(expected).unwrap(),
+            payload
+        );
+    }
+
+    assert_eq!(
+        GossipPayload::TYPES,
+        ["gossip", "gossip_ok", "ihave", "read", "read_ok"]
+    );
+}
+
+#[test]
+fn requests_are_paired_with_their_replies() {
+    let gossip = GossipPayload::Gossip { message: 3 };
+    assert_eq!(gossip.reply_type(), Some("gossip_ok"));
+    assert_eq!(gossip.request_type(), None);
+    assert!(!gossip.is_reply());
+
+    assert_eq!(GossipPayload::GossipOk.reply_type(), None);
+    assert_eq!(GossipPayload::GossipOk.request_type(), Some("gossip"));
+    assert!(GossipPayload::GossipOk.is_reply());
+
+    // Messages without a reply variant are neither
+    let ihave = GossipPayload::IHave { messages: vec![] };
+    assert_eq!(ihave.reply_type(), None);
+    assert!(!ihave.is_reply());
+}
+
+#[test]
+fn type_names_follow_the_enums_own_renaming() {
+    let txn = KebabPayload::Txn { ops: vec![1] };
+    assert_eq!(
+        serde_json::to_value(&txn).unwrap(),
+        json!({ "type": "txn", "ops": [1] })
+    );
+    assert_eq!(
+        serde_json::to_value(KebabPayload::TxnOk).unwrap(),
+        json!({ "type": "txn-ok" })
+    );
+    assert_eq!(
+        serde_json::to_value(KebabPayload::HeartBeat).unwrap(),
+        json!({ "type": "heart-beat" })
+    );
+    assert_eq!(
+        serde_json::from_value::<KebabPayload>(json!({ "type": "heartbeat" })).unwrap(),
+        KebabPayload::HeartBeat
+    );
+
+    assert_eq!(KebabPayload::TYPES, ["txn", "txn-ok", "heart-beat"]);
+    assert_eq!(KebabPayload::HeartBeat.type_name(), "heart-beat");
+    assert_eq!(txn.reply_type(), Some("txn-ok"));
+    assert_eq!(KebabPayload::TxnOk.request_type(), Some("txn"));
+}
+
+#[test]
+fn type_names_can_keep_the_variant_names() {
+    let lookup = PascalPayload::Lookup {
+        key: "k".to_string(),
+    };
+    assert_eq!(
+        serde_json::to_value(&lookup).unwrap(),
+        json!({ "type": "Lookup", "key": "k" })
+    );
+    assert_eq!(
+        serde_json::from_value::<PascalPayload>(json!({ "type": "LookupOk" })).unwrap(),
+        PascalPayload::LookupOk
+    );
+
+    assert_eq!(PascalPayload::TYPES, ["Lookup", "LookupOk"]);
+    assert_eq!(lookup.reply_type(), Some("LookupOk"));
+}
+
+#[test]
+fn derived_payloads_can_borrow_from_the_input() {
+    let line = r#"{"src":"c1","dest":"n0","body":{"type":"echo","msg_id":1,"echo":"hello"}}"#;
+    let message: Message<Borrowing> = serde_json::from_str(line).unwrap();
+
+    let Borrowing::Echo { echo } = &message.body.payload else {
+        panic!("unexpected payload {:?}", message.body.payload);
+    };
+    assert!(matches!(echo, Cow::Borrowed("hello")));
+    assert_eq!(message.body.payload.reply_type(), Some("echo_ok"));
+}
@@ -0,0 +1,144 @@
+use common::message::{MaelstromPayload, Message, MessageId};
+use common::node::{NodeId, RoutedNode};
+use common::outbox::Outbox;
+use common::router::{Router, NOT_SUPPORTED};
+use common::runtime::Runtime;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+mod support;
+use support::Sender;
+
+#[derive(Debug, MaelstromPayload)]
+enum Reply {
+    AddOk { sum: u64 },
+    ReadOk { value: u64 },
+}
+
+#[derive(Debug, Deserialize)]
+struct Add {
+    delta: u64,
+}
+
+/// Keeps a sum, with a handler for each message type it supports
+struct SumNode {
+    sender: Sender<Reply>,
+    sum: u64,
+}
+
+impl SumNode {
+    fn add(&mut self, message: Message<Add>) {
+        self.sum += message.body.payload.delta;
+        self.sender.send(
+            message.src,
+            message.body.msg_id,
+            Reply::AddOk { sum: self.sum },
+        );
+    }
+
+    fn read(&mut self, message: Message<Value>) {
+        self.sender.send(
+            message.src,
+            message.body.msg_id,
+            Reply::ReadOk { value: self.sum },
+        );
+    }
+}
+
+impl RoutedNode for SumNode {
+    type Output = Reply;
+
+    fn from_init(node_id: NodeId, _neighbors: Vec<NodeId>, tx: Outbox<Self::Output>) -> Self {
+        Self {
+            sender: Sender::new(node_id, tx),
+            sum: 0,
+        }
+    }
+
+    fn next_msg_id(&mut self) -> MessageId {
+        self.sender.next_msg_id()
+    }
+
+    fn route(router: &mut Router<Self>) {
+        router.handle("add", Self::add).handle("read", Self::read);
+    }
+}
+
+#[tokio::test]
+async fn messages_are_dispatched_by_type() {
+    let (stdin, mut stdin_writer) = tokio::io::duplex(1024);
+    let (stdout, stdout_reader) = tokio::io::duplex(1024);
+    tokio::spawn(Runtime::new().run_routed::<SumNode, _, _>(stdin, stdout));
+
+    let lines = [
+        json!({
+            "src": "c0", "dest": "n0",
+            "body": { "type": "init", "msg_id": 1, "node_id": "n0", "node_ids": ["n0"] }
+        }),
+        json!({ "src": "c1", "dest": "n0", "body": { "type": "add", "msg_id": 2, "delta": 3 } }),
+        json!({ "src": "c1", "dest": "n0", "body": { "type": "add", "msg_id": 3, "delta": 4 } }),
+        json!({ "src": "c1", "dest": "n0", "body": { "type": "read", "msg_id": 4 } }),
+        json!({ "src": "c1", "dest": "n0", "body": { "type": "subtract", "msg_id": 5, "delta": 1 } }),
+    ];
+
+    let mut replies = BufReader::new(stdout_reader).lines();
+    let mut bodies = vec![];
+    // Replies from the node and the runtime may be written in either order,
+    // so each message is only sent once the previous one was answered
+    for line in &lines {
+        stdin_writer
+            .write_all(format!("{line}\n").as_bytes())
+            .await
+            .unwrap();
+
+        let reply = replies.next_line().await.unwrap().unwrap();
+        bodies.push(serde_json::from_str::<Value>(&reply).unwrap()["body"].clone());
+    }
+
+    assert_eq!(bodies[0]["type"], "init_ok");
+    assert_eq!(bodies[1]["sum"], 3);
+    assert_eq!(bodies[2]["sum"], 7);
+    assert_eq!(bodies[3]["type"], "read_ok");
+    assert_eq!(bodies[3]["value"], 7);
+    assert_eq!(bodies[4]["type"], "error");
+    assert_eq!(bodies[4]["code"], NOT_SUPPORTED);
+    assert_eq!(bodies[4]["in_reply_to"], 5);
+}
+
+#[tokio::test]
+async fn only_requests_are_answered_as_not_supported() {
+    let (stdin, mut stdin_writer) = tokio::io::duplex(1024);
+    let (stdout, stdout_reader) = tokio::io::duplex(1024);
+    tokio::spawn(Runtime::new().run_routed::<SumNode, _, _>(stdin, stdout));
+
+    let lines = [
+        json!({
+            "src": "c0", "dest": "n0",
+            "body": { "type": "init", "msg_id": 1, "node_id": "n0", "node_ids": ["n0", "n1"] }
+        }),
+        json!({ "src": "n1", "dest": "n0", "body": { "type": "subtract_ok", "in_reply_to": 7 } }),
+        json!({
+            "src": "n1", "dest": "n0",
+            "body": { "type": "error", "msg_id": 8, "code": NOT_SUPPORTED, "text": "no" }
+        }),
+        json!({ "src": "c1", "dest": "n0", "body": { "type": "read", "msg_id": 2 } }),
+    ];
+
+    for line in &lines {
+        stdin_writer
+            .write_all(format!("{line}\n").as_bytes())
+            .await
+            .unwrap();
+    }
+    drop(stdin_writer);
+
+    let mut replies = BufReader::new(stdout_reader).lines();
+    let mut bodies = vec![];
+    while let Some(reply) = replies.next_line().await.unwrap() {
+        bodies.push(serde_json::from_str::<Value>(&reply).unwrap()["body"].clone());
+    }
+
+    let types: Vec<_> = bodies.iter().map(|body| body["type"].clone()).collect();
+    assert_eq!(types, ["init_ok", "read_ok"]);
+}
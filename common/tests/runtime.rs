@@ -0,0 +1,219 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use common::message::{Message, MessageBody, MessageId};
+use common::node::{dispatch_key, ConcurrentNode, Node, NodeId};
+use common::outbox::Outbox;
+use common::runtime::Runtime;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+mod support;
+use support::Sender;
+
+/// Echo requests that borrowed their text from the input line
+static BORROWED: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+enum Request<'a> {
+    Echo {
+        #[serde(borrow)]
+        echo: Cow<'a, str>,
+    },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+enum Reply {
+    EchoOk { echo: String, borrowed: bool },
+}
+
+struct BorrowingNode {
+    sender: Sender<Reply>,
+}
+
+impl<'de> Node<'de> for BorrowingNode {
+    type Payload = Request<'de>;
+    type Output = Reply;
+
+    fn handle_message(&mut self, message: Message<Self::Payload>) {
+        let Request::Echo { echo } = message.body.payload;
+        let borrowed = matches!(echo, Cow::Borrowed(_));
+        if borrowed {
+            BORROWED.fetch_add(1, Ordering::SeqCst);
+        }
+
+        self.sender.send(
+            message.src,
+            message.body.msg_id,
+            Reply::EchoOk {
+                echo: echo.into_owned(),
+                borrowed,
+            },
+        );
+    }
+
+    fn from_init(node_id: NodeId, _neighbors: Vec<NodeId>, tx: Outbox<Self::Output>) -> Self {
+        Self {
+            sender: Sender::new(node_id, tx),
+        }
+    }
+
+    fn next_msg_id(&mut self) -> MessageId {
+        self.sender.next_msg_id()
+    }
+}
+
+#[tokio::test]
+async fn payloads_can_borrow_from_the_input_line() {
+    let (stdin, mut stdin_writer) = tokio::io::duplex(1024);
+    let (stdout, stdout_reader) = tokio::io::duplex(1024);
+    tokio::spawn(Runtime::new().run::<BorrowingNode, _, _>(stdin, stdout));
+
+    let lines = [
+        json!({
+            "src": "c0", "dest": "n0",
+            "body": { "type": "init", "msg_id": 1, "node_id": "n0", "node_ids": ["n0"] }
+        }),
+        json!({ "src": "c1", "dest": "n0", "body": { "type": "echo", "msg_id": 2, "echo": "plain" } }),
+        // Escapes must be unescaped into an owned string
+        json!({ "src": "c1", "dest": "n0", "body": { "type": "echo", "msg_id": 3, "echo": "a\"b" } }),
+    ];
+    for line in &lines {
+        stdin_writer
+            .write_all(format!("{line}\n").as_bytes())
+            .await
+            .unwrap();
+    }
+
+    let mut replies = BufReader::new(stdout_reader).lines();
+    let mut bodies = vec![];
+    for _ in 0..lines.len() {
+        let line = replies.next_line().await.unwrap().unwrap();
+        bodies.push(serde_json::from_str::<serde_json::Value>(&line).unwrap()["body"].clone());
+    }
+
+    assert_eq!(bodies[0]["type"], "init_ok");
+    assert_eq!(
+        bodies[1],
+        json!({ "type": "echo_ok", "msg_id": 2, "in_reply_to": 2, "echo": "plain", "borrowed": true })
+    );
+    assert_eq!(
+        bodies[2],
+        json!({ "type": "echo_ok", "msg_id": 3, "in_reply_to": 3, "echo": "a\"b", "borrowed": false })
+    );
+    assert_eq!(BORROWED.load(Ordering::SeqCst), 1);
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+enum CountPayload {
+    Count { n: u64 },
+    CountOk { seen: Vec<u64> },
+}
+
+/// Replies to every count with the counts seen so far from its sender
+struct CountingNode {
+    id: NodeId,
+    curr_msg_id: AtomicU64,
+    seen: Mutex<HashMap<NodeId, Vec<u64>>>,
+    tx: Outbox<CountPayload>,
+}
+
+impl ConcurrentNode for CountingNode {
+    type Payload = CountPayload;
+    type Output = CountPayload;
+
+    fn handle_message(&self, message: Message<Self::Payload>) {
+        let CountPayload::Count { n } = message.body.payload else {
+            return;
+        };
+
+        let seen = {
+            let mut seen = self.seen.lock().expect("poisoned lock");
+            let seen = seen.entry(message.src.clone()).or_default();
+            seen.push(n);
+            seen.clone()
+        };
+
+        self.tx
+            .send(Message {
+                src: self.id.clone(),
+                dest: message.src,
+                body: MessageBody {
+                    msg_id: Some(self.next_msg_id()),
+                    in_reply_to: message.body.msg_id,
+                    payload: CountPayload::CountOk { seen },
+                },
+            })
+            .expect("failed sending message");
+    }
+
+    fn from_init(node_id: NodeId, _neighbors: Vec<NodeId>, tx: Outbox<Self::Output>) -> Self {
+        Self {
+            id: node_id,
+            curr_msg_id: Default::default(),
+            seen: Default::default(),
+            tx,
+        }
+    }
+
+    fn next_msg_id(&self) -> MessageId {
+        self.curr_msg_id.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn dispatch_key(&self, message: &Message<Self::Payload>) -> Option<u64> {
+        Some(dispatch_key(&message.src))
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_nodes_handle_each_key_in_order() {
+    let (stdin, mut stdin_writer) = tokio::io::duplex(64 * 1024);
+    let (stdout, stdout_reader) = tokio::io::duplex(64 * 1024);
+    let runtime = Runtime::new().workers(4);
+    let node = tokio::spawn(runtime.run_concurrent::<CountingNode, _, _>(stdin, stdout));
+
+    let mut input = json!({
+        "src": "c0", "dest": "n0",
+        "body": { "type": "init", "msg_id": 0, "node_id": "n0", "node_ids": ["n0"] }
+    })
+    .to_string();
+    input.push('\n');
+    for n in 0..100 {
+        for client in ["c1", "c2", "c3"] {
+            let line = json!({
+                "src": client, "dest": "n0", "body": { "type": "count", "msg_id": n + 1, "n": n }
+            });
+            input.push_str(&format!("{line}\n"));
+        }
+    }
+    stdin_writer.write_all(input.as_bytes()).await.unwrap();
+    drop(stdin_writer);
+    node.await.unwrap();
+
+    let mut replies = BufReader::new(stdout_reader).lines();
+    let mut longest: HashMap<String, Vec<u64>> = HashMap::new();
+    for _ in 0..301 {
+        let line = replies.next_line().await.unwrap().unwrap();
+        let message: Message<serde_json::Value> = serde_json::from_str(&line).unwrap();
+        if let Some(seen) = message.body.payload.get("seen") {
+            let seen: Vec<u64> = serde_json::from_value(seen.clone()).unwrap();
+            let longest = longest.entry(message.dest).or_default();
+            if seen.len() > longest.len() {
+                *longest = seen;
+            }
+        }
+    }
+
+    for client in ["c1", "c2", "c3"] {
+        assert_eq!(longest[client], (0..100).collect::<Vec<_>>());
+    }
+}
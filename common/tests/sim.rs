@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use common::message::{Message, MessageId};
+use common::node::{Node, NodeId};
+use common::outbox::Outbox;
+use common::sim::{Cluster, Faults, Partition, Simulation};
+use serde::{Deserialize, Serialize};
+
+mod support;
+use support::Sender;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+enum Payload {
+    Echo {
+        echo: String,
+    },
+    EchoOk {
+        echo: String,
+    },
+    /// Asks the receiver to ping `to` and reply once it has answered
+    Relay {
+        to: NodeId,
+    },
+    RelayOk {
+        by: NodeId,
+    },
+    Ping,
+    Pong,
+}
+
+/// Replies to echoes, and relays pings to other nodes on behalf of clients
+struct RelayNode {
+    sender: Sender<Payload>,
+    /// Client relay requests, keyed by the ID of the ping sent for them
+    relays: HashMap<MessageId, (NodeId, Option<MessageId>)>,
+}
+
+impl<'de> Node<'de> for RelayNode {
+    type Payload = Payload;
+    type Output = Payload;
+
+    fn handle_message(&mut self, message: Message<Self::Payload>) {
+        match message.body.payload {
+            Payload::Echo { echo } => {
+                self.sender
+                    .send(message.src, message.body.msg_id, Payload::EchoOk { echo });
+            }
+            Payload::Relay { to } => {
+                let ping = self.sender.send(to, None, Payload::Ping);
+                self.relays.insert(ping, (message.src, message.body.msg_id));
+            }
+            Payload::Ping => {
+                self.sender
+                    .send(message.src, message.body.msg_id, Payload::Pong);
+            }
+            Payload::Pong => {
+                let in_reply_to = message.body.in_reply_to.expect("pong without ping");
+                let (client, client_msg_id) = self.relays.remove(&in_reply_to).unwrap();
+                self.sender
+                    .send(client, client_msg_id, Payload::RelayOk { by: message.src });
+            }
+            Payload::EchoOk { .. } | Payload::RelayOk { .. } => {}
+        }
+    }
+
+    fn from_init(node_id: NodeId, _neighbors: Vec<NodeId>, tx: Outbox<Payload>) -> Self {
+        Self {
+            sender: Sender::new(node_id, tx),
+            relays: HashMap::new(),
+        }
+    }
+
+    fn next_msg_id(&mut self) -> MessageId {
+        self.sender.next_msg_id()
+    }
+}
+
+#[tokio::test]
+async fn client_requests_receive_replies() {
+    let mut cluster = Cluster::start::<RelayNode>(3).await;
+
+    assert_eq!(cluster.node_ids(), ["n0", "n1", "n2"]);
+
+    for node_id in cluster.node_ids().to_vec() {
+        let reply: Payload = cluster
+            .request(
+                &node_id,
+                Payload::Echo {
+                    echo: node_id.clone(),
+                },
+            )
+            .await;
+
+        assert_eq!(reply, Payload::EchoOk { echo: node_id });
+    }
+}
+
+#[tokio::test]
+async fn messages_are_routed_between_nodes() {
+    let mut cluster = Cluster::start::<RelayNode>(2).await;
+
+    let reply: Payload = cluster
+        .request(
+            "n0",
+            Payload::Relay {
+                to: "n1".to_string(),
+            },
+        )
+        .await;
+
+    assert_eq!(
+        reply,
+        Payload::RelayOk {
+            by: "n1".to_string()
+        }
+    );
+}
+
+#[tokio::test]
+async fn replies_are_matched_to_requests() {
+    let mut cluster = Cluster::start::<RelayNode>(1).await;
+
+    let first = cluster.send(
+        "n0",
+        Payload::Echo {
+            echo: "first".to_string(),
+        },
+    );
+    let second = cluster.send(
+        "n0",
+        Payload::Echo {
+            echo: "second".to_string(),
+        },
+    );
+
+    let reply = cluster.reply_to::<Payload>(second).await;
+    assert_eq!(
+        reply.body.payload,
+        Payload::EchoOk {
+            echo: "second".to_string()
+        }
+    );
+
+    let reply = cluster.reply_to::<Payload>(first).await;
+    assert_eq!(
+        reply.body.payload,
+        Payload::EchoOk {
+            echo: "first".to_string()
+        }
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn partitions_cut_links_until_healed() {
+    let mut cluster = Cluster::start::<RelayNode>(3).await;
+    let relay = |to: &str| Payload::Relay { to: to.to_string() };
+
+    cluster.partition(Partition::isolate(&"n1".to_string(), cluster.node_ids()));
+
+    let msg_id = cluster.send("n0", relay("n1"));
+    let reply = tokio::time::timeout(Duration::from_secs(1), cluster.reply_to::<Payload>(msg_id));
+    assert!(reply.await.is_err());
+
+    let reply: Payload = cluster.request("n0", relay("n2")).await;
+    assert_eq!(
+        reply,
+        Payload::RelayOk {
+            by: "n2".to_string()
+        }
+    );
+
+    cluster.heal();
+
+    let reply: Payload = cluster.request("n0", relay("n1")).await;
+    assert_eq!(
+        reply,
+        Payload::RelayOk {
+            by: "n1".to_string()
+        }
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn faults_apply_only_between_nodes() {
+    let mut cluster = Cluster::start::<RelayNode>(2).await;
+
+    cluster.set_faults(Faults {
+        drop: 1.0,
+        ..Default::default()
+    });
+
+    let reply: Payload = cluster
+        .request(
+            "n0",
+            Payload::Echo {
+                echo: "hi".to_string(),
+            },
+        )
+        .await;
+    assert_eq!(
+        reply,
+        Payload::EchoOk {
+            echo: "hi".to_string()
+        }
+    );
+
+    let msg_id = cluster.send(
+        "n0",
+        Payload::Relay {
+            to: "n1".to_string(),
+        },
+    );
+    let reply = tokio::time::timeout(Duration::from_secs(1), cluster.reply_to::<Payload>(msg_id));
+    assert!(reply.await.is_err());
+}
+
+#[test]
+fn partitions_cut_the_expected_links() {
+    let nodes: Vec<NodeId> = (0..5).map(|i| format!("n{i}")).collect();
+    let is_cut = |partition: &Partition, a: usize, b: usize| partition.is_cut(&nodes[a], &nodes[b]);
+
+    let partition = Partition::majority_minority(&nodes);
+    assert!(!is_cut(&partition, 0, 2));
+    assert!(!is_cut(&partition, 3, 4));
+    assert!(is_cut(&partition, 2, 3));
+    assert!(is_cut(&partition, 4, 0));
+
+    let partition = Partition::bridge(&nodes);
+    assert!(!is_cut(&partition, 0, 2));
+    assert!(!is_cut(&partition, 2, 4));
+    assert!(is_cut(&partition, 1, 3));
+    assert!(is_cut(&partition, 4, 0));
+}
+
+#[test]
+#[should_panic(expected = "a bridge needs at least one node")]
+fn bridges_need_a_node() {
+    Partition::bridge(&[]);
+}
+
+#[test]
+#[should_panic(expected = "drop probability must be between 0 and 1, not 1.5")]
+fn fault_probabilities_are_validated() {
+    Simulation::new(0).run(|simulation| async move {
+        let cluster = simulation.cluster::<RelayNode>(1).await;
+        cluster.set_faults(Faults {
+            drop: 1.5,
+            ..Default::default()
+        });
+    });
+}
+
+/// Relays many pings over a faulty network, returning the order in which the
+/// client received the replies and the virtual time at which it did
+fn relay_under_faults(simulation: Simulation) -> Vec<(Option<MessageId>, Duration)> {
+    simulation.run(|simulation| async move {
+        let mut cluster = simulation.cluster::<RelayNode>(3).await;
+        let start = tokio::time::Instant::now();
+
+        cluster.set_faults(Faults {
+            delay: 0.5,
+            reorder: 0.5,
+            max_delay: Duration::from_millis(100),
+            ..Default::default()
+        });
+
+        for i in 0..30 {
+            let node = format!("n{}", i % 3);
+            let to = format!("n{}", (i + 1) % 3);
+            cluster.send(&node, Payload::Relay { to });
+        }
+
+        let mut replies = vec![];
+        for _ in 0..30 {
+            let reply = cluster.recv::<Payload>().await;
+            replies.push((reply.body.in_reply_to, start.elapsed()));
+        }
+
+        replies
+    })
+}
+
+#[test]
+fn simulations_replay_exactly_from_a_seed() {
+    let seed = Simulation::from_env().seed();
+
+    assert_eq!(
+        relay_under_faults(Simulation::new(seed)),
+        relay_under_faults(Simulation::new(seed))
+    );
+}
+
+#[test]
+fn timers_run_in_virtual_time() {
+    let elapsed = Simulation::new(0).run(|_| async {
+        let start = std::time::Instant::now();
+        tokio::time::sleep(Duration::from_secs(3600)).await;
+        start.elapsed()
+    });
+
+    assert!(elapsed < Duration::from_secs(60));
+}
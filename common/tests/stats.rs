@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use common::message::{Message, MessageBody};
+use common::stats::Recorder;
+use common::trace::{Direction, TraceEntry};
+use serde_json::{json, Value};
+
+fn message(
+    src: &str,
+    dest: &str,
+    msg_id: u64,
+    in_reply_to: Option<u64>,
+    kind: &str,
+) -> Message<Value> {
+    Message {
+        src: src.to_string(),
+        dest: dest.to_string(),
+        body: MessageBody {
+            msg_id: Some(msg_id),
+            in_reply_to,
+            payload: json!({ "type": kind }),
+        },
+    }
+}
+
+fn recorder() -> Recorder {
+    Recorder::new().servers(["n0", "n1", "n2"].map(String::from))
+}
+
+#[test]
+fn latencies_use_nearest_rank_percentiles() {
+    let mut recorder = recorder();
+
+    for i in 1..=100 {
+        recorder.observe(0, &message("c1", "n0", i, None, "read"));
+        recorder.observe(i * 1000, &message("n0", "c1", i, Some(i), "read_ok"));
+    }
+
+    let stats = recorder.stats();
+    let latencies = stats.latencies.unwrap();
+    assert_eq!(stats.operations, 100);
+    assert_eq!(latencies.p50, Duration::from_micros(50));
+    assert_eq!(latencies.p95, Duration::from_micros(95));
+    assert_eq!(latencies.p99, Duration::from_micros(99));
+    assert_eq!(latencies.max, Duration::from_micros(100));
+}
+
+#[test]
+fn server_messages_are_broken_down_by_type_and_sender() {
+    let mut recorder = recorder();
+
+    recorder.observe(0, &message("c1", "n0", 1, None, "broadcast"));
+    recorder.observe(1, &message("n0", "n1", 1, None, "gossip"));
+    recorder.observe(2, &message("n0", "n2", 2, None, "gossip"));
+    recorder.observe(3, &message("n1", "n0", 1, Some(1), "gossip_ok"));
+    recorder.observe(4, &message("n0", "seq-kv", 3, None, "read"));
+    // Clients are told apart by not being in the cluster, whatever their name
+    recorder.observe(5, &message("n0", "node-client", 5, None, "gossip"));
+    recorder.observe(5, &message("n0", "c1", 4, Some(1), "broadcast_ok"));
+
+    let stats = recorder.stats();
+    assert_eq!(stats.operations, 1);
+    assert_eq!(stats.server_messages(), 3);
+    assert_eq!(stats.msgs_per_op(), 3.0);
+    assert_eq!(stats.by_type["gossip"], 2);
+    assert_eq!(stats.by_type["gossip_ok"], 1);
+    assert_eq!(stats.by_node["n0"], 2);
+    assert_eq!(stats.by_node["n1"], 1);
+}
+
+#[test]
+fn unanswered_requests_have_no_latency() {
+    let mut recorder = recorder();
+
+    recorder.observe(0, &message("c1", "n0", 1, None, "read"));
+
+    let stats = recorder.stats();
+    assert_eq!(stats.operations, 0);
+    assert_eq!(stats.latencies, None);
+    assert_eq!(stats.msgs_per_op(), 0.0);
+}
+
+#[test]
+fn traces_count_each_server_message_once() {
+    let entry = |time, direction, message: Message<Value>| TraceEntry {
+        time,
+        direction,
+        message: serde_json::to_value(message).unwrap(),
+    };
+    // The cluster is learned from the init message at the start of a trace
+    let mut init = message("c0", "n0", 1, None, "init");
+    init.body.payload["node_ids"] = json!(["n0", "n1"]);
+    let n0 = vec![
+        entry(0, Direction::Inbound, init),
+        entry(
+            10,
+            Direction::Inbound,
+            message("c1", "n0", 1, None, "broadcast"),
+        ),
+        entry(
+            20,
+            Direction::Outbound,
+            message("n0", "n1", 1, None, "gossip"),
+        ),
+        entry(
+            30,
+            Direction::Outbound,
+            message("n0", "c1", 2, Some(1), "broadcast_ok"),
+        ),
+    ];
+    let n1 = vec![entry(
+        5,
+        Direction::Inbound,
+        message("n0", "n1", 1, None, "gossip"),
+    )];
+
+    let mut recorder = Recorder::new();
+    recorder.observe_trace(&n0);
+    recorder.observe_trace(&n1);
+
+    let stats = recorder.stats();
+    assert_eq!(stats.operations, 1);
+    assert_eq!(stats.latencies.unwrap().max, Duration::from_nanos(20));
+    assert_eq!(stats.server_messages(), 1);
+}
@@ -0,0 +1,49 @@
+//! Boilerplate shared by the nodes the tests run
+
+#![allow(dead_code)]
+
+use common::message::{Message, MessageBody, MessageId};
+use common::node::NodeId;
+use common::outbox::Outbox;
+
+/// What every test node keeps to send messages: its ID, the ID of the last
+/// message it sent and its outbox
+pub struct Sender<P> {
+    pub id: NodeId,
+    curr_msg_id: MessageId,
+    tx: Outbox<P>,
+}
+
+impl<P> Sender<P> {
+    /// The sender for the node `id` handed `tx` on `init`
+    pub fn new(id: NodeId, tx: Outbox<P>) -> Self {
+        Self {
+            id,
+            curr_msg_id: Default::default(),
+            tx,
+        }
+    }
+
+    pub fn next_msg_id(&mut self) -> MessageId {
+        self.curr_msg_id = self.curr_msg_id.checked_add(1).expect("id overflow");
+        self.curr_msg_id
+    }
+
+    /// Sends `payload` to `dest`, returning the ID of the sent message
+    pub fn send(&mut self, dest: NodeId, in_reply_to: Option<MessageId>, payload: P) -> MessageId {
+        let msg_id = self.next_msg_id();
+        self.tx
+            .send(Message {
+                src: self.id.clone(),
+                dest,
+                body: MessageBody {
+                    msg_id: Some(msg_id),
+                    in_reply_to,
+                    payload,
+                },
+            })
+            .expect("failed sending message");
+
+        msg_id
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::{BTreeMap, HashMap};
+
+use common::codec::Codec;
+use common::message::{Message, MessageId};
+use common::node::{Node, NodeId};
+use common::outbox::Outbox;
+use common::runtime::Runtime;
+use common::transport::{TcpConfig, TcpTransport};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+mod support;
+use support::Sender;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+enum RelayPayload {
+    Relay { to: NodeId },
+    RelayOk,
+    Ping,
+    Pong,
+}
+
+/// Replies to a relay once the node it names has answered a ping
+struct RelayNode {
+    sender: Sender<RelayPayload>,
+    /// The relay each ping was sent for, by the ping's ID
+    relays: HashMap<MessageId, (NodeId, Option<MessageId>)>,
+}
+
+impl Node<'_> for RelayNode {
+    type Payload = RelayPayload;
+    type Output = RelayPayload;
+
+    fn handle_message(&mut self, message: Message<Self::Payload>) {
+        match message.body.payload {
+            RelayPayload::Relay { to } => {
+                let ping_id = self.sender.send(to, None, RelayPayload::Ping);
+                self.relays
+                    .insert(ping_id, (message.src, message.body.msg_id));
+            }
+            RelayPayload::Ping => {
+                self.sender
+                    .send(message.src, message.body.msg_id, RelayPayload::Pong);
+            }
+            RelayPayload::Pong => {
+                let ping_id = message.body.in_reply_to.expect("pong without ping");
+                if let Some((client, relay_id)) = self.relays.remove(&ping_id) {
+                    self.sender.send(client, relay_id, RelayPayload::RelayOk);
+                }
+            }
+            RelayPayload::RelayOk => {}
+        }
+    }
+
+    fn from_init(node_id: NodeId, _neighbors: Vec<NodeId>, tx: Outbox<Self::Output>) -> Self {
+        Self {
+            sender: Sender::new(node_id, tx),
+            relays: Default::default(),
+        }
+    }
+
+    fn next_msg_id(&mut self) -> MessageId {
+        self.sender.next_msg_id()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn nodes_exchange_messages_with_peers_and_clients_over_tcp() {
+    for codec in [Codec::Json, Codec::MessagePack, Codec::Cbor] {
+        relay(codec).await;
+    }
+}
+
+/// Relays a client's request from one node to another and back
+async fn relay(codec: Codec) {
+    let mut listeners = BTreeMap::new();
+    for node in ["n0", "n1"] {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listeners.insert(node.to_string(), listener);
+    }
+    let config = TcpConfig {
+        nodes: listeners
+            .iter()
+            .map(|(node, listener)| (node.clone(), listener.local_addr().unwrap()))
+            .collect(),
+        codec,
+    };
+    let n0 = config.nodes["n0"];
+
+    for (node, listener) in listeners {
+        let transport = TcpTransport::with_listener(config.clone(), node, listener);
+        tokio::spawn(
+            transport.serve(|reader, writer| Runtime::new().run::<RelayNode, _, _>(reader, writer)),
+        );
+    }
+
+    let (reader, mut writer) = TcpStream::connect(n0).await.unwrap().into_split();
+    let relay =
+        json!({ "src": "c1", "dest": "n0", "body": { "type": "relay", "msg_id": 7, "to": "n1" } });
+    writer
+        .write_all(format!("{relay}\n").as_bytes())
+        .await
+        .unwrap();
+
+    let line = BufReader::new(reader)
+        .lines()
+        .next_line()
+        .await
+        .unwrap()
+        .unwrap();
+    let reply: Value = serde_json::from_str(&line).unwrap();
+
+    assert_eq!(reply["src"], "n0");
+    assert_eq!(reply["dest"], "c1");
+    assert_eq!(reply["body"]["type"], "relay_ok");
+    assert_eq!(reply["body"]["in_reply_to"], 7);
+}
@@ -0,0 +1,164 @@
+use std::path::PathBuf;
+
+use common::message::{Message, MessageId};
+use common::node::{Node, NodeId};
+use common::outbox::Outbox;
+use common::runtime::Runtime;
+use common::trace::{self, Direction, Tracer};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+mod support;
+use support::Sender;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+enum Payload {
+    Echo { echo: String },
+    EchoOk { echo: String },
+}
+
+struct EchoNode {
+    sender: Sender<Payload>,
+}
+
+impl<'de> Node<'de> for EchoNode {
+    type Payload = Payload;
+    type Output = Payload;
+
+    fn handle_message(&mut self, message: Message<Self::Payload>) {
+        if let Payload::Echo { echo } = message.body.payload {
+            self.sender
+                .send(message.src, message.body.msg_id, Payload::EchoOk { echo });
+        }
+    }
+
+    fn from_init(node_id: NodeId, _neighbors: Vec<NodeId>, tx: Outbox<Self::Output>) -> Self {
+        Self {
+            sender: Sender::new(node_id, tx),
+        }
+    }
+
+    fn next_msg_id(&mut self) -> MessageId {
+        self.sender.next_msg_id()
+    }
+}
+
+fn trace_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("{name}-{}.jsonl", std::process::id()))
+}
+
+/// Runs an echo node with tracing enabled, sending it an init message followed
+/// by `echoes`, and returns the recorded trace
+async fn record(name: &str, echoes: &[&str]) -> Vec<trace::TraceEntry> {
+    let path = trace_path(name);
+    let (stdin, mut stdin_writer) = tokio::io::duplex(1024);
+    let (stdout, stdout_reader) = tokio::io::duplex(1024);
+    let runtime = Runtime::new().trace(Tracer::create(&path).unwrap());
+    tokio::spawn(runtime.run::<EchoNode, _, _>(stdin, stdout));
+
+    let init = json!({
+        "src": "c0",
+        "dest": "n0",
+        "body": { "type": "init", "msg_id": 1, "node_id": "n0", "node_ids": ["n0"] }
+    });
+    let mut lines = vec![init];
+    for (i, echo) in echoes.iter().enumerate() {
+        lines.push(json!({
+            "src": "c0",
+            "dest": "n0",
+            "body": { "type": "echo", "msg_id": i + 2, "echo": echo }
+        }));
+    }
+
+    let mut replies = BufReader::new(stdout_reader).lines();
+    for line in &lines {
+        stdin_writer
+            .write_all(format!("{line}\n").as_bytes())
+            .await
+            .unwrap();
+        replies.next_line().await.unwrap().unwrap();
+    }
+
+    let file = std::fs::File::open(&path).unwrap();
+    let entries = trace::read_trace(std::io::BufReader::new(file)).unwrap();
+    std::fs::remove_file(path).unwrap();
+    entries
+}
+
+#[tokio::test(start_paused = true)]
+async fn trace_records_both_directions_in_order() {
+    let entries = record("trace-order", &["a", "b"]).await;
+
+    let directions: Vec<_> = entries.iter().map(|e| e.direction).collect();
+    assert_eq!(
+        directions,
+        [Direction::Inbound, Direction::Outbound].repeat(3)
+    );
+    assert!(entries.windows(2).all(|w| w[0].time <= w[1].time));
+    assert_eq!(entries[3].message["body"]["echo"], "a");
+    assert_eq!(entries[5].message["body"]["in_reply_to"], 3);
+}
+
+#[tokio::test(start_paused = true)]
+async fn replay_reproduces_recorded_output() {
+    let entries = record("trace-replay", &["a", "b"]).await;
+
+    let diff = trace::replay::<EchoNode>(&entries).await;
+
+    assert!(diff.is_empty(), "{diff:?}");
+}
+
+#[tokio::test(start_paused = true)]
+async fn replay_reports_divergent_output() {
+    let mut entries = record("trace-diverge", &["a"]).await;
+    let recorded = entries[3].message.clone();
+    entries[3].message["body"]["echo"] = json!("z");
+
+    let diff = trace::replay::<EchoNode>(&entries).await;
+
+    assert_eq!(diff.missing, vec![entries[3].message.clone()]);
+    assert_eq!(diff.unexpected, vec![recorded]);
+}
+
+#[test]
+fn diagram_orders_receives_after_sends_and_highlights_unreplied_requests() {
+    let entry = |time, direction, message: serde_json::Value| trace::TraceEntry {
+        time,
+        direction,
+        message,
+    };
+    let request = json!({
+        "src": "c1", "dest": "n0", "body": { "type": "broadcast", "msg_id": 1, "message": 5 }
+    });
+    let gossip = json!({
+        "src": "n0", "dest": "n1", "body": { "type": "gossip", "msg_id": 1, "message": 5 }
+    });
+    let reply = json!({
+        "src": "n0", "dest": "c1", "body": { "type": "broadcast_ok", "msg_id": 2, "in_reply_to": 1 }
+    });
+    let n0 = vec![
+        entry(100, Direction::Inbound, request),
+        entry(200, Direction::Outbound, gossip.clone()),
+        entry(300, Direction::Outbound, reply),
+    ];
+    // n1's clock lags behind n0's, but its receive must still follow the send
+    let n1 = vec![entry(0, Direction::Inbound, gossip)];
+
+    let diagram = trace::sequence_diagram(&[n1, n0]);
+
+    assert_eq!(
+        diagram,
+        "sequenceDiagram\n    \
+         participant c1\n    \
+         participant n0\n    \
+         participant n1\n    \
+         c1->>n0: broadcast id=1\n    \
+         rect rgb(255, 210, 210)\n    \
+         n0-xn1: gossip id=1 (no reply)\n    \
+         end\n    \
+         n0-->>c1: broadcast_ok id=2 re=1\n"
+    );
+}
@@ -0,0 +1,101 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use common::codec::{self, Codec};
+use common::message::{Message, MessageBody};
+use common::outbox::{self, Overflow};
+use common::writer::MessageWriter;
+use serde_json::{json, Value};
+use tokio::io::AsyncWrite;
+
+/// Records every write and flush
+#[derive(Default)]
+struct Recording {
+    writes: Vec<Vec<u8>>,
+    flushes: usize,
+}
+
+impl AsyncWrite for Recording {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.writes.push(buf.to_vec());
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.flushes += 1;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn message(n: u64) -> Message<Value> {
+    Message {
+        src: "n0".to_string(),
+        dest: "n1".to_string(),
+        body: MessageBody {
+            msg_id: Some(n),
+            in_reply_to: None,
+            payload: json!({ "type": "count", "n": n }),
+        },
+    }
+}
+
+#[tokio::test]
+async fn queued_messages_are_coalesced_into_one_write() {
+    let (tx, mut rx) = outbox::channel(16, Overflow::Reject);
+    for n in 0..3 {
+        tx.send(message(n)).unwrap();
+    }
+    drop(tx);
+
+    let mut recording = Recording::default();
+    MessageWriter::new(&mut recording).drain(&mut rx).await;
+
+    assert_eq!(recording.writes.len(), 1);
+    assert_eq!(recording.flushes, 1);
+
+    let lines: Vec<Message<Value>> = String::from_utf8(recording.writes.concat())
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let payloads: Vec<_> = lines.iter().map(|m| m.body.payload["n"].clone()).collect();
+    assert_eq!(payloads, vec![0, 1, 2]);
+}
+
+#[tokio::test]
+async fn only_messages_to_servers_use_the_binary_codec() {
+    let (tx, mut rx) = outbox::channel(16, Overflow::Reject);
+    for dest in ["n1", "node-client", "c1"] {
+        tx.send(Message {
+            dest: dest.to_string(),
+            ..message(0)
+        })
+        .unwrap();
+    }
+    drop(tx);
+
+    let mut recording = Recording::default();
+    MessageWriter::new(&mut recording)
+        .codec(Codec::MessagePack)
+        .servers(["n0".to_string(), "n1".to_string()])
+        .drain(&mut rx)
+        .await;
+
+    let stream = recording.writes.concat();
+    let mut reader = tokio::io::BufReader::new(stream.as_slice());
+    let mut frame = vec![];
+    let mut codecs = vec![];
+    while codec::read_frame(&mut reader, &mut frame).await.unwrap() {
+        codecs.push(Codec::of(&frame));
+    }
+    assert_eq!(codecs, [Codec::MessagePack, Codec::Json, Codec::Json]);
+}
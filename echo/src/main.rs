@@ -1,53 +1,48 @@
-use common::message::{Message, MessageBody, MessageId};
-use common::node::{Node, NodeId};
+use common::message::{MaelstromPayload, Message, MessageBody, MessageId};
+use common::node::{NodeId, RoutedNode};
+use common::outbox::Outbox;
+use common::router::Router;
 use common::runtime::Runtime;
-use tokio::sync::mpsc::UnboundedSender;
+use serde::Deserialize;
 
-use serde::{Deserialize, Serialize};
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(tag = "type")]
-#[serde(rename_all = "snake_case")]
+#[derive(Debug, Clone, MaelstromPayload)]
 enum MessagePayload {
-    Echo { echo: String },
     EchoOk { echo: String },
 }
 
+#[derive(Debug, Deserialize)]
+struct Echo {
+    echo: String,
+}
+
 struct EchoNode {
     id: NodeId,
     curr_msg_id: MessageId,
-    tx: UnboundedSender<Message<MessagePayload>>,
+    tx: Outbox<MessagePayload>,
 }
 
-impl<'a> Node<'a> for EchoNode {
-    type Payload = MessagePayload;
+impl EchoNode {
+    fn echo(&mut self, message: Message<Echo>) {
+        let next_msg_id = self.next_msg_id();
 
-    fn handle_message(&mut self, message: common::message::Message<Self::Payload>) {
-        match message.body.payload {
-            Self::Payload::Echo { echo } => {
-                let next_msg_id = self.next_msg_id();
-
-                self.tx
-                    .send(Message {
-                        src: self.id.clone(),
-                        dest: message.src,
-                        body: MessageBody {
-                            msg_id: Some(next_msg_id),
-                            in_reply_to: message.body.msg_id,
-                            payload: Self::Payload::EchoOk { echo },
-                        },
-                    })
-                    .expect("failed sending message");
-            }
-            Self::Payload::EchoOk { .. } => {}
-        }
+        self.tx.send_or_drop(Message {
+            src: self.id.clone(),
+            dest: message.src,
+            body: MessageBody {
+                msg_id: Some(next_msg_id),
+                in_reply_to: message.body.msg_id,
+                payload: MessagePayload::EchoOk {
+                    echo: message.body.payload.echo,
+                },
+            },
+        });
     }
+}
+
+impl RoutedNode for EchoNode {
+    type Output = MessagePayload;
 
-    fn from_init(
-        node_id: NodeId,
-        _neighbors: Vec<NodeId>,
-        tx: tokio::sync::mpsc::UnboundedSender<common::message::Message<Self::Payload>>,
-    ) -> Self {
+    fn from_init(node_id: NodeId, _neighbors: Vec<NodeId>, tx: Outbox<Self::Output>) -> Self {
         Self {
             id: node_id,
             curr_msg_id: 0,
@@ -59,6 +54,10 @@ impl<'a> Node<'a> for EchoNode {
         self.curr_msg_id = self.curr_msg_id.checked_add(1).expect("ids exhausted");
         self.curr_msg_id
     }
+
+    fn route(router: &mut Router<Self>) {
+        router.handle("echo", Self::echo);
+    }
 }
 
 #[tokio::main]
@@ -66,5 +65,5 @@ async fn main() {
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
 
-    Runtime::start::<EchoNode, _, _>(stdin, stdout).await;
+    Runtime::start_routed::<EchoNode, _, _>(stdin, stdout).await;
 }
@@ -0,0 +1,190 @@
+//! Grow-only counter nodes, replicated between nodes or stored in `seq-kv`.
+
+pub mod seq_kv;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::crdt::{Crdt, DeltaReplicator, DeltaSync, GCounter};
+use common::message::{MaelstromPayload, Message, MessageBody, MessageId};
+use common::node::{Node, NodeId};
+use common::outbox::Outbox;
+
+/// How often each node ships unacknowledged counter deltas to its neighbors
+const GOSSIP_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How many deltas to buffer before lagging neighbors are sent the full state
+const DELTA_CAPACITY: usize = 64;
+
+#[derive(Debug, Clone, MaelstromPayload)]
+pub enum MessagePayload {
+    Read,
+    ReadOk {
+        value: u64,
+    },
+    Add {
+        delta: u32,
+    },
+    AddOk,
+    /// Carries counter state the receiver has not yet acknowledged
+    Replicate {
+        sync: DeltaSync<GCounter>,
+    },
+    ReplicateOk {
+        seq: u64,
+    },
+}
+
+/// A grow-only counter node.
+///
+/// Each node only ever increments its own entry in the counter, and entries
+/// from other nodes are merged by taking the maximum. Since merging is
+/// idempotent, replicated state can be duplicated, reordered or retransmitted
+/// without any add being counted more than once.
+#[derive(Debug, Clone)]
+pub struct GCounterNode {
+    id: NodeId,
+    replicator: Arc<Mutex<DeltaReplicator<GCounter>>>,
+    curr_msg_id: MessageId,
+    tx: Outbox<MessagePayload>,
+}
+
+impl GCounterNode {
+    /// Periodically sends every neighbor the counter state it has not yet
+    /// acknowledged, so that nodes converge once any partition between them
+    /// heals
+    fn spawn_gossip(&self, neighbors: Vec<NodeId>) {
+        let node_id = self.id.clone();
+        let tx = self.tx.clone();
+        let replicator = Arc::clone(&self.replicator);
+
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(GOSSIP_INTERVAL).await;
+
+                let syncs: Vec<_> = {
+                    let replicator = replicator.lock().expect("poisoned lock");
+                    neighbors
+                        .iter()
+                        .filter_map(|neighbor| Some((neighbor, replicator.sync_for(neighbor)?)))
+                        .collect()
+                };
+
+                for (neighbor, sync) in syncs {
+                    // Unacknowledged deltas are resent on the next round if a
+                    // full outbox rejects this one
+                    tx.send_or_drop(Message {
+                        src: node_id.clone(),
+                        dest: neighbor.clone(),
+                        body: MessageBody {
+                            msg_id: None,
+                            in_reply_to: None,
+                            payload: MessagePayload::Replicate { sync },
+                        },
+                    });
+                }
+            }
+        });
+    }
+}
+
+impl<'de> Node<'de> for GCounterNode {
+    type Payload = MessagePayload;
+    type Output = MessagePayload;
+
+    fn handle_message(&mut self, message: Message<Self::Payload>) {
+        if self.id != message.dest {
+            return;
+        }
+
+        match message.body.payload {
+            MessagePayload::Add { delta } => {
+                {
+                    let mut replicator = self.replicator.lock().expect("poisoned lock");
+                    let mut counter = GCounter::new();
+                    counter.increment(
+                        &self.id,
+                        replicator.state().get(&self.id) + u64::from(delta),
+                    );
+                    replicator.apply(counter);
+                }
+
+                let msg_id = self.next_msg_id();
+
+                self.tx.send_or_drop(Message {
+                    src: self.id.clone(),
+                    dest: message.src,
+                    body: MessageBody {
+                        msg_id: Some(msg_id),
+                        in_reply_to: message.body.msg_id,
+                        payload: MessagePayload::AddOk,
+                    },
+                });
+            }
+            MessagePayload::Read => {
+                let msg_id = self.next_msg_id();
+                let value = self
+                    .replicator
+                    .lock()
+                    .expect("poisoned lock")
+                    .state()
+                    .value();
+
+                self.tx.send_or_drop(Message {
+                    src: self.id.clone(),
+                    dest: message.src,
+                    body: MessageBody {
+                        msg_id: Some(msg_id),
+                        in_reply_to: message.body.msg_id,
+                        payload: MessagePayload::ReadOk { value },
+                    },
+                });
+            }
+            MessagePayload::Replicate { sync } => {
+                let seq = self
+                    .replicator
+                    .lock()
+                    .expect("poisoned lock")
+                    .receive(&message.src, sync);
+                let msg_id = self.next_msg_id();
+
+                self.tx.send_or_drop(Message {
+                    src: self.id.clone(),
+                    dest: message.src,
+                    body: MessageBody {
+                        msg_id: Some(msg_id),
+                        in_reply_to: message.body.msg_id,
+                        payload: MessagePayload::ReplicateOk { seq },
+                    },
+                });
+            }
+            MessagePayload::ReplicateOk { seq } => {
+                self.replicator
+                    .lock()
+                    .expect("poisoned lock")
+                    .ack(&message.src, seq);
+            }
+            MessagePayload::AddOk | MessagePayload::ReadOk { .. } => {}
+        }
+    }
+
+    fn from_init(node_id: NodeId, neighbors: Vec<NodeId>, tx: Outbox<Self::Output>) -> Self {
+        let node = Self {
+            id: node_id,
+            replicator: Arc::new(Mutex::new(DeltaReplicator::new(
+                neighbors.clone(),
+                DELTA_CAPACITY,
+            ))),
+            curr_msg_id: Default::default(),
+            tx,
+        };
+
+        node.spawn_gossip(neighbors);
+        node
+    }
+
+    fn next_msg_id(&mut self) -> MessageId {
+        self.curr_msg_id = self.curr_msg_id.checked_add(1).expect("id overflow");
+        self.curr_msg_id
+    }
+}
@@ -0,0 +1,361 @@
+//! A grow-only counter that stores its value in Maelstrom's `seq-kv` service.
+//!
+//! Adds read the current value and `cas` in the incremented one, retrying from
+//! the read whenever another node wins the race. Since `seq-kv` only promises
+//! sequential consistency, a plain read may return a stale value, so reads
+//! confirm the value they observed by `cas`ing it onto itself.
+//!
+//! Operations the service fails or does not answer in time are retried from
+//! the read, except for an add's `cas`, which may have been applied without a
+//! reply and is instead reported to the client as indefinite.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::message::{MaelstromPayload, Message, MessageBody, MessageId};
+use common::node::{Node, NodeId};
+use common::outbox::{Outbox, SendError};
+use tokio::time::Instant;
+
+/// The node ID of the Maelstrom service holding the counter
+const SERVICE: &str = "seq-kv";
+
+/// The key the counter is stored under
+const KEY: &str = "counter";
+
+/// How long to wait for the service to answer before giving up on a request
+const KV_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Maelstrom's error codes
+mod error_code {
+    /// The outcome of a request is unknown, as it was not answered in time
+    pub const TIMEOUT: u32 = 0;
+    /// The request definitely did not take effect, and may be retried
+    pub const TEMPORARILY_UNAVAILABLE: u32 = 11;
+    pub const KEY_DOES_NOT_EXIST: u32 = 20;
+}
+
+#[derive(Debug, Clone, MaelstromPayload)]
+pub enum MessagePayload {
+    /// A read of the counter from a client, or of `key` from the KV service
+    Read {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        key: Option<String>,
+    },
+    ReadOk {
+        value: u64,
+    },
+    Add {
+        delta: u32,
+    },
+    AddOk,
+    Cas {
+        key: String,
+        from: u64,
+        to: u64,
+        create_if_not_exists: bool,
+    },
+    CasOk,
+    Error {
+        code: u32,
+        #[serde(default)]
+        text: Option<String>,
+    },
+}
+
+/// The client request an outstanding KV operation is being performed for
+#[derive(Debug, Clone)]
+struct Request {
+    src: NodeId,
+    msg_id: Option<MessageId>,
+}
+
+/// A KV operation awaiting a reply from the service
+#[derive(Debug, Clone)]
+enum Pending {
+    /// Reading the current value in order to add `delta` to it
+    AddRead { request: Request, delta: u32 },
+    /// Swapping in the current value plus `delta`
+    AddCas { request: Request, delta: u32 },
+    /// Reading the current value for a client read
+    ReadRead { request: Request },
+    /// Confirming `value` is current by swapping it onto itself
+    ReadCas { request: Request, value: u64 },
+}
+
+#[derive(Debug, Clone)]
+pub struct SeqKvCounterNode {
+    /// Shared with the task expiring operations the service does not answer
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug)]
+struct State {
+    id: NodeId,
+    curr_msg_id: MessageId,
+    tx: Outbox<MessagePayload>,
+    /// Outstanding KV operations and when they were sent, keyed by the
+    /// message ID of the request sent to the service
+    pending: HashMap<MessageId, (Instant, Pending)>,
+}
+
+impl State {
+    fn next_msg_id(&mut self) -> MessageId {
+        self.curr_msg_id = self.curr_msg_id.checked_add(1).expect("id overflow");
+        self.curr_msg_id
+    }
+
+    fn send_to_service(&mut self, payload: MessagePayload, pending: Pending) {
+        let msg_id = self.next_msg_id();
+        let message = Message {
+            src: self.id.clone(),
+            dest: SERVICE.to_string(),
+            body: MessageBody {
+                msg_id: Some(msg_id),
+                in_reply_to: None,
+                payload,
+            },
+        };
+
+        match self.tx.send(message) {
+            Ok(()) => {
+                self.pending.insert(msg_id, (Instant::now(), pending));
+            }
+            Err(SendError::Full(_)) => self.abandon(pending),
+            Err(SendError::Closed(_)) => panic!("failed sending message"),
+        }
+    }
+
+    fn kv_read(&mut self, pending: Pending) {
+        self.send_to_service(
+            MessagePayload::Read {
+                key: Some(KEY.to_string()),
+            },
+            pending,
+        );
+    }
+
+    fn kv_cas(&mut self, from: u64, to: u64, pending: Pending) {
+        self.send_to_service(
+            MessagePayload::Cas {
+                key: KEY.to_string(),
+                from,
+                to,
+                create_if_not_exists: true,
+            },
+            pending,
+        );
+    }
+
+    fn reply(&mut self, request: Request, payload: MessagePayload) {
+        let msg_id = self.next_msg_id();
+
+        self.tx.send_or_drop(Message {
+            src: self.id.clone(),
+            dest: request.src,
+            body: MessageBody {
+                msg_id: Some(msg_id),
+                in_reply_to: request.msg_id,
+                payload,
+            },
+        });
+    }
+
+    /// Continues `pending` now that the counter is known to hold `value`
+    fn on_read(&mut self, pending: Pending, value: u64) {
+        match pending {
+            Pending::AddRead { request, delta } => {
+                self.kv_cas(
+                    value,
+                    value + u64::from(delta),
+                    Pending::AddCas { request, delta },
+                );
+            }
+            Pending::ReadRead { request } => {
+                self.kv_cas(value, value, Pending::ReadCas { request, value });
+            }
+            // A stale reply, as the service only reads for reads
+            Pending::AddCas { .. } | Pending::ReadCas { .. } => {}
+        }
+    }
+
+    /// Restarts `pending` from a fresh read after a failed operation
+    fn retry(&mut self, pending: Pending) {
+        match pending {
+            Pending::AddRead { request, delta } | Pending::AddCas { request, delta } => {
+                self.kv_read(Pending::AddRead { request, delta });
+            }
+            Pending::ReadRead { request } | Pending::ReadCas { request, .. } => {
+                self.kv_read(Pending::ReadRead { request });
+            }
+        }
+    }
+
+    /// Fails `pending` right away, as its request was dropped by a full
+    /// outbox and the service never saw it
+    fn abandon(&mut self, pending: Pending) {
+        let (Pending::AddRead { request, .. }
+        | Pending::AddCas { request, .. }
+        | Pending::ReadRead { request }
+        | Pending::ReadCas { request, .. }) = pending;
+
+        self.reply(
+            request,
+            MessagePayload::Error {
+                code: error_code::TEMPORARILY_UNAVAILABLE,
+                text: Some("outbox is full".to_string()),
+            },
+        );
+    }
+
+    /// Gives up waiting for the reply to `pending`
+    fn expire(&mut self, pending: Pending) {
+        match pending {
+            // The counter may or may not have been swapped, so retrying could
+            // count the add twice
+            Pending::AddCas { request, .. } => self.reply(
+                request,
+                MessagePayload::Error {
+                    code: error_code::TIMEOUT,
+                    text: Some("seq-kv did not answer in time".to_string()),
+                },
+            ),
+            pending => self.retry(pending),
+        }
+    }
+
+    /// Expires every operation sent to the service more than [`KV_TIMEOUT`]
+    /// ago
+    fn expire_overdue(&mut self) {
+        let now = Instant::now();
+        let overdue: Vec<_> = self
+            .pending
+            .iter()
+            .filter(|(_, (sent, _))| now.duration_since(*sent) >= KV_TIMEOUT)
+            .map(|(msg_id, _)| *msg_id)
+            .collect();
+
+        for msg_id in overdue {
+            let (_, pending) = self.pending.remove(&msg_id).expect("overdue operation");
+            self.expire(pending);
+        }
+    }
+
+    /// Removes the operation `in_reply_to` if `reply` is a reply it expects.
+    /// Other replies are stale, and leave the operation pending.
+    fn take_pending(&mut self, in_reply_to: MessageId, reply: &MessagePayload) -> Option<Pending> {
+        let (_, pending) = self.pending.get(&in_reply_to)?;
+        let expected = matches!(
+            (reply, pending),
+            (
+                MessagePayload::ReadOk { .. },
+                Pending::AddRead { .. } | Pending::ReadRead { .. }
+            ) | (
+                MessagePayload::CasOk,
+                Pending::AddCas { .. } | Pending::ReadCas { .. }
+            ) | (MessagePayload::Error { .. }, _)
+        );
+        if !expected {
+            return None;
+        }
+
+        self.pending
+            .remove(&in_reply_to)
+            .map(|(_, pending)| pending)
+    }
+
+    fn handle_message(&mut self, message: Message<MessagePayload>) {
+        if self.id != message.dest {
+            return;
+        }
+
+        // Only the service answers KV operations, whatever a client's message
+        // claims to reply to
+        let pending = match message.body.in_reply_to {
+            Some(in_reply_to) if message.src == SERVICE => {
+                self.take_pending(in_reply_to, &message.body.payload)
+            }
+            _ => None,
+        };
+
+        match (message.body.payload, pending) {
+            (MessagePayload::Add { delta }, _) => {
+                let request = Request {
+                    src: message.src,
+                    msg_id: message.body.msg_id,
+                };
+                self.kv_read(Pending::AddRead { request, delta });
+            }
+            (MessagePayload::Read { .. }, _) => {
+                let request = Request {
+                    src: message.src,
+                    msg_id: message.body.msg_id,
+                };
+                self.kv_read(Pending::ReadRead { request });
+            }
+            (MessagePayload::ReadOk { value }, Some(pending)) => self.on_read(pending, value),
+            (MessagePayload::CasOk, Some(Pending::AddCas { request, .. })) => {
+                self.reply(request, MessagePayload::AddOk);
+            }
+            (MessagePayload::CasOk, Some(Pending::ReadCas { request, value })) => {
+                self.reply(request, MessagePayload::ReadOk { value });
+            }
+            (
+                MessagePayload::Error { code, .. },
+                Some(pending @ (Pending::AddRead { .. } | Pending::ReadRead { .. })),
+            ) if code == error_code::KEY_DOES_NOT_EXIST => self.on_read(pending, 0),
+            // Any other failure, most commonly a `cas` precondition failure
+            // because another node updated the counter first
+            (MessagePayload::Error { .. }, Some(pending)) => self.retry(pending),
+            // Replies to operations that are unknown, already completed or
+            // expired
+            _ => {}
+        }
+    }
+}
+
+impl SeqKvCounterNode {
+    /// Periodically expires operations the service has not answered, so that
+    /// requests lost on the way to or from it are not left pending forever
+    fn spawn_expiry(&self) {
+        let state = Arc::clone(&self.state);
+
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(KV_TIMEOUT).await;
+                state.lock().expect("poisoned lock").expire_overdue();
+            }
+        });
+    }
+}
+
+impl<'de> Node<'de> for SeqKvCounterNode {
+    type Payload = MessagePayload;
+    type Output = MessagePayload;
+
+    fn handle_message(&mut self, message: Message<Self::Payload>) {
+        self.state
+            .lock()
+            .expect("poisoned lock")
+            .handle_message(message);
+    }
+
+    fn from_init(node_id: NodeId, _neighbors: Vec<NodeId>, tx: Outbox<Self::Output>) -> Self {
+        let node = Self {
+            state: Arc::new(Mutex::new(State {
+                id: node_id,
+                curr_msg_id: Default::default(),
+                tx,
+                pending: HashMap::new(),
+            })),
+        };
+
+        node.spawn_expiry();
+        node
+    }
+
+    fn next_msg_id(&mut self) -> MessageId {
+        self.state.lock().expect("poisoned lock").next_msg_id()
+    }
+}
@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use common::sim::{Cluster, Faults, Partition, Simulation};
+use g_counter::GCounterNode;
+use serde_json::{json, Value};
+
+/// Reads `node` until its counter reaches `expected`, returning the value
+/// read last if it did not within `timeout`
+async fn read_until(cluster: &mut Cluster, node: &str, expected: u64, timeout: Duration) -> u64 {
+    let deadline = tokio::time::Instant::now() + timeout;
+
+    loop {
+        let reply: Value = cluster.request(node, json!({ "type": "read" })).await;
+        let value = reply["value"].as_u64().expect("value");
+
+        if value == expected || tokio::time::Instant::now() >= deadline {
+            return value;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+#[test]
+fn counters_converge_despite_duplication_and_partitions() {
+    Simulation::from_env().run(|simulation| async move {
+        let mut cluster = simulation.cluster::<GCounterNode>(5).await;
+        let nodes = cluster.node_ids().to_vec();
+
+        cluster.set_faults(Faults {
+            duplicate: 0.5,
+            delay: 0.3,
+            reorder: 0.3,
+            max_delay: Duration::from_millis(300),
+            ..Default::default()
+        });
+        cluster.partition(Partition::majority_minority(&nodes));
+
+        let mut sum = 0;
+        for delta in 1..=20 {
+            let node = &nodes[delta as usize % nodes.len()];
+            let reply: Value = cluster
+                .request(node, json!({ "type": "add", "delta": delta }))
+                .await;
+            assert_eq!(reply["type"], "add_ok");
+            sum += delta;
+        }
+
+        // Neither side of the partition can have seen the other's adds
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert!(read_until(&mut cluster, &nodes[0], sum, Duration::ZERO).await < sum);
+
+        cluster.heal();
+        for node in &nodes {
+            let value = read_until(&mut cluster, node, sum, Duration::from_secs(30)).await;
+            assert_eq!(value, sum, "{node} did not converge");
+        }
+    });
+}
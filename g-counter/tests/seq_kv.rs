@@ -0,0 +1,233 @@
+use std::time::Duration;
+
+use common::message::{MaelstromPayload, Message, MessageBody, MessageId};
+use common::node::{Node, NodeId};
+use common::outbox::Outbox;
+use common::sim::Simulation;
+use g_counter::seq_kv::SeqKvCounterNode;
+use serde_json::{json, Value};
+
+/// How long a node has to answer, however slow the service is
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, MaelstromPayload)]
+enum KvPayload {
+    Read {
+        key: String,
+    },
+    ReadOk {
+        value: u64,
+    },
+    Cas {
+        key: String,
+        from: u64,
+        to: u64,
+        create_if_not_exists: bool,
+    },
+    CasOk,
+    Error {
+        code: u32,
+    },
+}
+
+/// Stands in for Maelstrom's `seq-kv`, holding a single value, but fails
+/// some requests and ignores others
+struct FlakyKv {
+    id: NodeId,
+    curr_msg_id: MessageId,
+    tx: Outbox<KvPayload>,
+    value: Option<u64>,
+    requests: u64,
+}
+
+impl<'de> Node<'de> for FlakyKv {
+    type Payload = KvPayload;
+    type Output = KvPayload;
+
+    fn handle_message(&mut self, message: Message<Self::Payload>) {
+        self.requests += 1;
+
+        let reply = match message.body.payload {
+            // Lost on the way, leaving the node to time out
+            KvPayload::Read { .. } if self.requests.is_multiple_of(4) => return,
+            // Temporarily unavailable
+            KvPayload::Read { .. } if self.requests.is_multiple_of(5) => {
+                KvPayload::Error { code: 11 }
+            }
+            KvPayload::Read { .. } => match self.value {
+                Some(value) => KvPayload::ReadOk { value },
+                None => KvPayload::Error { code: 20 },
+            },
+            // Spurious precondition failures, as if another writer had won
+            KvPayload::Cas { .. } if self.requests.is_multiple_of(3) => {
+                KvPayload::Error { code: 22 }
+            }
+            KvPayload::Cas {
+                from,
+                to,
+                create_if_not_exists,
+                ..
+            } => match self.value {
+                Some(value) if value == from => {
+                    self.value = Some(to);
+                    KvPayload::CasOk
+                }
+                None if create_if_not_exists => {
+                    self.value = Some(to);
+                    KvPayload::CasOk
+                }
+                _ => KvPayload::Error { code: 22 },
+            },
+            KvPayload::ReadOk { .. } | KvPayload::CasOk | KvPayload::Error { .. } => return,
+        };
+
+        let msg_id = self.next_msg_id();
+        self.tx
+            .send(Message {
+                src: self.id.clone(),
+                dest: message.src,
+                body: MessageBody {
+                    msg_id: Some(msg_id),
+                    in_reply_to: message.body.msg_id,
+                    payload: reply,
+                },
+            })
+            .expect("failed sending message");
+    }
+
+    fn from_init(node_id: NodeId, _neighbors: Vec<NodeId>, tx: Outbox<Self::Output>) -> Self {
+        Self {
+            id: node_id,
+            curr_msg_id: Default::default(),
+            tx,
+            value: None,
+            requests: 0,
+        }
+    }
+
+    fn next_msg_id(&mut self) -> MessageId {
+        self.curr_msg_id = self.curr_msg_id.checked_add(1).expect("id overflow");
+        self.curr_msg_id
+    }
+}
+
+/// Stands in for `seq-kv` with a counter of 0, but never answers a `cas`.
+/// It answers it with a stale `read_ok` instead, and tells the client the ID
+/// of the `cas` in a `stalled` message.
+struct StallingKv {
+    id: NodeId,
+    curr_msg_id: MessageId,
+    tx: Outbox<Value>,
+}
+
+impl StallingKv {
+    fn send(&mut self, dest: &str, in_reply_to: Option<MessageId>, payload: Value) {
+        let msg_id = self.next_msg_id();
+        self.tx
+            .send(Message {
+                src: self.id.clone(),
+                dest: dest.to_string(),
+                body: MessageBody {
+                    msg_id: Some(msg_id),
+                    in_reply_to,
+                    payload,
+                },
+            })
+            .expect("failed sending message");
+    }
+}
+
+impl<'de> Node<'de> for StallingKv {
+    type Payload = Value;
+    type Output = Value;
+
+    fn handle_message(&mut self, message: Message<Self::Payload>) {
+        let stale = json!({ "type": "read_ok", "value": 0 });
+
+        match message.body.payload["type"].as_str() {
+            Some("read") => self.send(&message.src, message.body.msg_id, stale),
+            Some("cas") => {
+                self.send(&message.src, message.body.msg_id, stale);
+                let stalled = json!({ "type": "stalled", "cas": message.body.msg_id });
+                self.send("c0", None, stalled);
+            }
+            // Sends `message` to `to` as if it replied to `re`
+            Some("forge") => {
+                let payload = &message.body.payload;
+                let in_reply_to = payload["re"].as_u64();
+                let to = payload["to"].as_str().expect("forge without to");
+                self.send(to, in_reply_to, payload["message"].clone());
+                self.send(
+                    &message.src,
+                    message.body.msg_id,
+                    json!({ "type": "forge_ok" }),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    fn from_init(node_id: NodeId, _neighbors: Vec<NodeId>, tx: Outbox<Self::Output>) -> Self {
+        Self {
+            id: node_id,
+            curr_msg_id: Default::default(),
+            tx,
+        }
+    }
+
+    fn next_msg_id(&mut self) -> MessageId {
+        self.curr_msg_id = self.curr_msg_id.checked_add(1).expect("id overflow");
+        self.curr_msg_id
+    }
+}
+
+#[test]
+fn adds_survive_failed_and_lost_kv_requests() {
+    Simulation::from_env().run(|simulation| async move {
+        let mut cluster = simulation.cluster::<SeqKvCounterNode>(3).await;
+        cluster.add_node::<FlakyKv>("seq-kv").await;
+
+        for delta in 1..=10 {
+            let node = format!("n{}", delta % 3);
+            let reply: Value = cluster
+                .request(&node, json!({ "type": "add", "delta": delta }))
+                .await;
+            assert_eq!(reply["type"], "add_ok");
+        }
+
+        for node in cluster.node_ids().to_vec() {
+            let reply: Value = cluster.request(&node, json!({ "type": "read" })).await;
+            assert_eq!(reply["value"], 55);
+        }
+    });
+}
+
+#[test]
+fn stale_and_colliding_replies_leave_kv_operations_pending() {
+    Simulation::from_env().run(|simulation| async move {
+        let mut cluster = simulation.cluster::<SeqKvCounterNode>(1).await;
+        cluster.add_node::<StallingKv>("seq-kv").await;
+        cluster.add_node::<StallingKv>("forger").await;
+
+        let add = cluster.send("n0", json!({ "type": "add", "delta": 1 }));
+        let stalled: Message<Value> = cluster.recv().await;
+        assert_eq!(stalled.body.payload["type"], "stalled");
+
+        // A client request that claims to reply to the outstanding `cas`
+        let forge = json!({
+            "type": "forge",
+            "to": "n0",
+            "re": stalled.body.payload["cas"],
+            "message": { "type": "add", "delta": 2 },
+        });
+        let forged: Value = cluster.request("forger", forge).await;
+        assert_eq!(forged["type"], "forge_ok");
+
+        // The add is only given up on once its `cas` times out
+        let reply: Message<Value> = tokio::time::timeout(REPLY_TIMEOUT, cluster.reply_to(add))
+            .await
+            .expect("the add was never answered");
+        assert_eq!(reply.body.payload["type"], "error");
+        assert_eq!(reply.body.payload["code"], 0);
+    });
+}
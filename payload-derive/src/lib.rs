@@ -0,0 +1,302 @@
+//! `#[derive(MaelstromPayload)]`, re-exported by `common::message`.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::meta::ParseNestedMeta;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Expr, Lit, LitStr, Variant};
+
+/// Derives `Serialize`, `Deserialize` and `common::message::Payload` for an
+/// enum of message payloads.
+///
+/// Variants are tagged by the `type` field, named in snake case unless the
+/// enum sets its own `#[serde(rename_all = "...")]` or a variant is renamed
+/// with `#[serde(rename = "...")]`. Any other serde attributes on the enum,
+/// its variants or their fields apply as usual. A variant `Foo` is paired
+/// with a variant `FooOk` as its request and reply.
+#[proc_macro_derive(MaelstromPayload, attributes(serde))]
+pub fn derive_maelstrom_payload(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let Data::Enum(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "MaelstromPayload can only be derived for enums",
+        ));
+    };
+    if let Some(param) = input.generics.type_params().next() {
+        return Err(syn::Error::new_spanned(
+            param,
+            "MaelstromPayload does not support type parameters",
+        ));
+    }
+    if let Some(param) = input.generics.const_params().next() {
+        return Err(syn::Error::new_spanned(
+            param,
+            "MaelstromPayload does not support const parameters",
+        ));
+    }
+
+    let ident = &input.ident;
+    let remote = format_ident!("__{}Remote", ident);
+    let generics = &input.generics;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let remote_path = ident.to_string();
+    let serde_attrs = input.attrs.iter().filter(|attr| is_serde(attr));
+    let variants = data.variants.iter();
+
+    // Variants are only named in snake case by default, as serde rejects a
+    // second `rename_all`
+    let rename_all = rename_all(&input.attrs)?;
+    let default_rename_all = match rename_all {
+        Some(_) => quote!(),
+        None => quote!(#[serde(rename_all = "snake_case")]),
+    };
+    let case = rename_all.unwrap_or(Some(Case::Snake));
+
+    let idents: Vec<_> = data.variants.iter().map(|v| v.ident.to_string()).collect();
+    let names = data
+        .variants
+        .iter()
+        .map(|variant| type_name(variant, case))
+        .collect::<syn::Result<Vec<_>>>()?;
+    let name_of = |ident: &str| {
+        let index = idents.iter().position(|i| i == ident)?;
+        Some(names[index].clone())
+    };
+    let replies = idents
+        .iter()
+        .map(|ident| option(name_of(&format!("{ident}Ok"))));
+    let requests = idents
+        .iter()
+        .map(|ident| option(ident.strip_suffix("Ok").and_then(name_of)));
+    let patterns: Vec<_> = data
+        .variants
+        .iter()
+        .map(|variant| {
+            let variant = &variant.ident;
+            quote!(Self::#variant { .. })
+        })
+        .collect();
+
+    let lifetimes: Vec<_> = generics.lifetimes().map(|def| &def.lifetime).collect();
+    let de_generics = match lifetimes.as_slice() {
+        [] => quote!(<'de>),
+        lifetimes => quote!(<'de: #(#lifetimes)+*, #(#lifetimes),*>),
+    };
+
+    Ok(quote! {
+        const _: () = {
+            use ::common::__private::serde;
+
+            #[derive(serde::Serialize, serde::Deserialize)]
+            #[serde(crate = "::common::__private::serde")]
+            #[serde(remote = #remote_path)]
+            #[serde(tag = "type")]
+            #default_rename_all
+            #(#serde_attrs)*
+            enum #remote #generics #where_clause {
+                #(#variants),*
+            }
+
+            impl #impl_generics serde::Serialize for #ident #ty_generics #where_clause {
+                fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    #remote::serialize(self, serializer)
+                }
+            }
+
+            impl #de_generics serde::Deserialize<'de> for #ident #ty_generics #where_clause {
+                fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    #remote::deserialize(deserializer)
+                }
+            }
+
+            impl #impl_generics ::common::message::Payload for #ident #ty_generics #where_clause {
+                const TYPES: &'static [&'static str] = &[#(#names),*];
+
+                fn type_name(&self) -> &'static str {
+                    match self {
+                        #(#patterns => #names,)*
+                    }
+                }
+
+                fn reply_type(&self) -> Option<&'static str> {
+                    match self {
+                        #(#patterns => #replies,)*
+                    }
+                }
+
+                fn request_type(&self) -> Option<&'static str> {
+                    match self {
+                        #(#patterns => #requests,)*
+                    }
+                }
+            }
+        };
+    })
+}
+
+fn is_serde(attr: &Attribute) -> bool {
+    attr.path().is_ident("serde")
+}
+
+/// The name `variant` is serialized with, as serde would name it under the
+/// enum's `case`
+fn type_name(variant: &Variant, case: Option<Case>) -> syn::Result<String> {
+    let mut rename = None;
+
+    for attr in variant.attrs.iter().filter(|attr| is_serde(attr)) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                if let Some(name) = serialize_value(&meta)? {
+                    rename = Some(name.value());
+                }
+                Ok(())
+            } else {
+                skip(&meta)
+            }
+        })?;
+    }
+
+    let ident = variant.ident.to_string();
+    Ok(rename.unwrap_or_else(|| match case {
+        Some(case) => case.apply(&ident),
+        None => ident,
+    }))
+}
+
+/// The case the enum's own `rename_all` serializes variant names with, if it
+/// sets one. The case is `None` if it only renames them for deserializing.
+fn rename_all(attrs: &[Attribute]) -> syn::Result<Option<Option<Case>>> {
+    let mut case = None;
+
+    for attr in attrs.iter().filter(|attr| is_serde(attr)) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename_all") {
+                let name = serialize_value(&meta)?;
+                case = Some(name.map(|name| Case::parse(&name)).transpose()?);
+                Ok(())
+            } else {
+                skip(&meta)
+            }
+        })?;
+    }
+
+    Ok(case)
+}
+
+/// The value of an attribute such as `rename`, either given directly or as
+/// `rename(serialize = "...")`
+fn serialize_value(meta: &ParseNestedMeta) -> syn::Result<Option<LitStr>> {
+    if meta.input.peek(syn::Token![=]) {
+        return string(meta).map(Some);
+    }
+
+    let mut value = None;
+    meta.parse_nested_meta(|meta| {
+        if meta.path.is_ident("serialize") {
+            value = Some(string(&meta)?);
+            Ok(())
+        } else {
+            skip(&meta)
+        }
+    })?;
+
+    Ok(value)
+}
+
+fn string(meta: &ParseNestedMeta) -> syn::Result<LitStr> {
+    let Expr::Lit(expr) = meta.value()?.parse()? else {
+        return Err(meta.error("expected a string"));
+    };
+    let Lit::Str(value) = expr.lit else {
+        return Err(meta.error("expected a string"));
+    };
+
+    Ok(value)
+}
+
+/// Skips over an attribute this macro does not need to look at
+fn skip(meta: &ParseNestedMeta) -> syn::Result<()> {
+    if meta.input.peek(syn::Token![=]) {
+        meta.value()?.parse::<Expr>()?;
+    } else if meta.input.peek(syn::token::Paren) {
+        meta.parse_nested_meta(|meta| skip(&meta))?;
+    }
+
+    Ok(())
+}
+
+/// The cases serde's `rename_all` can rename variants to
+#[derive(Debug, Clone, Copy)]
+enum Case {
+    Lower,
+    Upper,
+    Pascal,
+    Camel,
+    Snake,
+    ScreamingSnake,
+    Kebab,
+    ScreamingKebab,
+}
+
+impl Case {
+    fn parse(name: &LitStr) -> syn::Result<Self> {
+        Ok(match name.value().as_str() {
+            "lowercase" => Self::Lower,
+            "UPPERCASE" => Self::Upper,
+            "PascalCase" => Self::Pascal,
+            "camelCase" => Self::Camel,
+            "snake_case" => Self::Snake,
+            "SCREAMING_SNAKE_CASE" => Self::ScreamingSnake,
+            "kebab-case" => Self::Kebab,
+            "SCREAMING-KEBAB-CASE" => Self::ScreamingKebab,
+            _ => return Err(syn::Error::new_spanned(name, "unknown rename_all case")),
+        })
+    }
+
+    /// Renames the `Pascal` variant `ident`
+    fn apply(self, ident: &str) -> String {
+        match self {
+            Self::Lower => ident.to_ascii_lowercase(),
+            Self::Upper => ident.to_ascii_uppercase(),
+            Self::Pascal => ident.to_string(),
+            Self::Camel => ident[..1].to_ascii_lowercase() + &ident[1..],
+            Self::Snake => snake_case(ident),
+            Self::ScreamingSnake => snake_case(ident).to_ascii_uppercase(),
+            Self::Kebab => snake_case(ident).replace('_', "-"),
+            Self::ScreamingKebab => snake_case(ident).to_ascii_uppercase().replace('_', "-"),
+        }
+    }
+}
+
+/// Converts a `Pascal` identifier to `snake_case`, as serde's
+/// `rename_all = "snake_case"` does
+fn snake_case(ident: &str) -> String {
+    let mut name = String::new();
+
+    for (index, c) in ident.char_indices() {
+        if c.is_uppercase() {
+            if index > 0 {
+                name.push('_');
+            }
+            name.push(c.to_ascii_lowercase());
+        } else {
+            name.push(c);
+        }
+    }
+
+    name
+}
+
+fn option(name: Option<String>) -> TokenStream2 {
+    match name {
+        Some(name) => quote!(Some(#name)),
+        None => quote!(None),
+    }
+}
@@ -0,0 +1,196 @@
+//! Node processes connected to each other, and to the runner's clients, by
+//! their stdin and stdout, over an in-memory [`Network`].
+
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::message::{Message, MessageBody, MessageId};
+use common::network::Network;
+use common::node::NodeId;
+use common::stats::{Recorder, Stats};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio::time::Instant;
+
+/// How long nodes are given to reply to setup requests such as `init`
+const SETUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The node ID setup requests are sent from
+pub const SETUP_CLIENT_ID: &str = "c0";
+
+pub struct Cluster {
+    node_ids: Vec<NodeId>,
+    router: Arc<Router>,
+    client_rx: UnboundedReceiver<Message<Value>>,
+    curr_msg_id: MessageId,
+    /// Killed when the cluster is dropped
+    _children: Vec<Child>,
+}
+
+impl Cluster {
+    /// Spawns `size` processes running `bin`, named `n0` through
+    /// `n{size - 1}`, and waits for each of them to complete the `init`
+    /// handshake
+    pub async fn start(bin: &Path, size: usize) -> Self {
+        let node_ids: Vec<NodeId> = (0..size).map(|i| format!("n{i}")).collect();
+        let (network, client_rx) = Network::start();
+        let mut stdouts = vec![];
+        let mut children = vec![];
+
+        for node_id in &node_ids {
+            let mut child = Command::new(bin)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::inherit())
+                .kill_on_drop(true)
+                .spawn()
+                .unwrap_or_else(|e| panic!("failed spawning {}: {e}", bin.display()));
+
+            let mut stdin = child.stdin.take().expect("piped stdin");
+            let (inbox, mut inbox_rx) = unbounded_channel::<Vec<u8>>();
+
+            tokio::spawn(async move {
+                while let Some(frame) = inbox_rx.recv().await {
+                    if stdin.write_all(&frame).await.is_err() {
+                        break;
+                    }
+                }
+            });
+
+            network.join(node_id.clone(), inbox);
+            stdouts.push((node_id.clone(), child.stdout.take().expect("piped stdout")));
+            children.push(child);
+        }
+
+        let router = Arc::new(Router {
+            network,
+            recorder: Mutex::new(Recorder::new().servers(node_ids.clone())),
+            start: Instant::now(),
+        });
+
+        for (node_id, stdout) in stdouts {
+            let router = router.clone();
+
+            tokio::spawn(async move {
+                let mut lines = BufReader::new(stdout).lines();
+
+                while let Ok(Some(line)) = lines.next_line().await {
+                    match serde_json::from_str(&line) {
+                        Ok(message) => router.route(message),
+                        Err(e) => eprintln!("{node_id} wrote malformed message ({e}): {line}"),
+                    }
+                }
+            });
+        }
+
+        let mut cluster = Self {
+            node_ids: node_ids.clone(),
+            router,
+            client_rx,
+            curr_msg_id: Default::default(),
+            _children: children,
+        };
+
+        for node_id in &node_ids {
+            cluster
+                .setup(
+                    node_id,
+                    json!({ "type": "init", "node_id": node_id, "node_ids": node_ids }),
+                )
+                .await;
+        }
+
+        cluster
+    }
+
+    pub fn node_ids(&self) -> &[NodeId] {
+        &self.node_ids
+    }
+
+    /// Sends `payload` from `src` to the node `dest`, returning the ID of the
+    /// sent message
+    pub fn send(&mut self, src: &str, dest: &str, payload: Value) -> MessageId {
+        self.curr_msg_id = self.curr_msg_id.checked_add(1).expect("id overflow");
+        assert!(
+            self.node_ids.iter().any(|node_id| node_id == dest),
+            "unknown node {dest}"
+        );
+
+        self.router.route(Message {
+            src: src.to_string(),
+            dest: dest.to_string(),
+            body: MessageBody {
+                msg_id: Some(self.curr_msg_id),
+                in_reply_to: None,
+                payload,
+            },
+        });
+
+        self.curr_msg_id
+    }
+
+    /// Statistics about every message routed since the cluster started, or
+    /// since they were last reset
+    pub fn stats(&self) -> Stats {
+        self.router.recorder.lock().expect("poisoned lock").stats()
+    }
+
+    pub fn reset_stats(&self) {
+        *self.router.recorder.lock().expect("poisoned lock") =
+            Recorder::new().servers(self.node_ids.clone());
+    }
+
+    /// Waits for the next message sent to a client
+    pub async fn recv(&mut self) -> Message<Value> {
+        self.client_rx.recv().await.expect("router stopped")
+    }
+
+    /// Sends `payload` to `dest` and waits for the reply, panicking if the
+    /// node does not reply in time or replies with an error
+    pub async fn setup(&mut self, dest: &str, payload: Value) -> Value {
+        let msg_id = self.send(SETUP_CLIENT_ID, dest, payload);
+
+        let reply = tokio::time::timeout(SETUP_TIMEOUT, async {
+            loop {
+                let message = self.recv().await;
+
+                if message.body.in_reply_to == Some(msg_id) {
+                    break message.body.payload;
+                }
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("{dest} did not reply to setup request {msg_id}"));
+
+        if reply["type"] == "error" {
+            panic!("{dest} rejected setup request {msg_id}: {reply}");
+        }
+
+        reply
+    }
+}
+
+/// Records every message on its way through the network
+struct Router {
+    network: Network,
+    recorder: Mutex<Recorder>,
+    start: Instant,
+}
+
+impl Router {
+    /// Hands `message` to the network, which delivers it to the node it is
+    /// addressed to, or to the clients if it is not addressed to a node
+    fn route(&self, message: Message<Value>) {
+        let time = self.start.elapsed().as_nanos() as u64;
+        self.recorder
+            .lock()
+            .expect("poisoned lock")
+            .observe(time, &message);
+
+        self.network.send(message);
+    }
+}
@@ -0,0 +1,6 @@
+//! Spawning node processes, routing messages between them and driving client
+//! workloads against them, for the `runner` binary.
+
+pub mod cluster;
+pub mod options;
+pub mod workload;
@@ -0,0 +1,131 @@
+//! A local stand-in for Maelstrom: spawns node processes, routes messages
+//! between them, and drives a client workload against them.
+//!
+//! `runner stats <trace>...` instead reports statistics for a run from the
+//! traces its nodes recorded with `RUNTIME_TRACE`, and `runner diagram
+//! <trace>...` renders those traces as a Mermaid sequence diagram.
+//!
+//! Services such as `seq-kv` are not provided, so nodes must not depend on
+//! them.
+
+use std::io::Write;
+
+use checker::history::{Event, EventKind};
+use common::stats::Recorder;
+use common::trace::{self, TraceEntry};
+use runner::cluster::Cluster;
+use runner::options::{Options, WorkloadKind};
+use runner::workload::{self, Broadcast, Echo, GCounter, UniqueIds, Workload};
+use serde::Serialize;
+
+#[tokio::main]
+async fn main() {
+    let mut args = std::env::args().skip(1).peekable();
+
+    match args.peek().map(String::as_str) {
+        Some("stats") => return print_trace_stats(args.skip(1)),
+        Some("diagram") => {
+            let traces = read_traces(args.skip(1));
+            print!("{}", trace::sequence_diagram(&traces));
+            return;
+        }
+        _ => {}
+    }
+
+    let options = match Options::parse(args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{message}");
+            std::process::exit(2);
+        }
+    };
+
+    let valid = match options.workload {
+        WorkloadKind::Echo => run(Echo, &options).await,
+        WorkloadKind::UniqueIds => run(UniqueIds, &options).await,
+        WorkloadKind::Broadcast => run(Broadcast::new(options.topology), &options).await,
+        WorkloadKind::GCounter => run(GCounter, &options).await,
+    };
+
+    if !valid {
+        std::process::exit(1);
+    }
+}
+
+/// Runs `workload` against a fresh cluster and reports the results, returning
+/// whether the history was valid
+async fn run<W: Workload>(mut workload: W, options: &Options) -> bool {
+    let mut cluster = Cluster::start(&options.bin, options.node_count).await;
+    let node_ids = cluster.node_ids().to_vec();
+    let history = workload::run(&mut workload, &mut cluster, options).await;
+    print!("{}", cluster.stats());
+    drop(cluster);
+
+    if let Some(path) = &options.history {
+        write_history(path, &history).expect("failed writing history");
+    }
+
+    let count = |kind| history.iter().filter(|e| e.kind == kind).count();
+    println!(
+        "{} operations: {} ok, {} failed, {} unknown",
+        count(EventKind::Invoke),
+        count(EventKind::Ok),
+        count(EventKind::Fail),
+        count(EventKind::Info)
+    );
+
+    match workload.check(&history, &node_ids) {
+        Ok(anomalies) if anomalies.is_empty() => {
+            println!("valid");
+            true
+        }
+        Ok(anomalies) => {
+            for anomaly in &anomalies {
+                println!("anomaly: {anomaly}");
+            }
+            println!("invalid: {} anomalies", anomalies.len());
+            false
+        }
+        Err(e) => {
+            println!("malformed history: {e}");
+            false
+        }
+    }
+}
+
+/// Prints statistics for the node traces at `paths`
+fn print_trace_stats(paths: impl Iterator<Item = String>) {
+    let mut recorder = Recorder::new();
+
+    for entries in read_traces(paths) {
+        recorder.observe_trace(&entries);
+    }
+
+    print!("{}", recorder.stats());
+}
+
+fn read_traces(paths: impl Iterator<Item = String>) -> Vec<Vec<TraceEntry>> {
+    paths
+        .map(|path| {
+            let file = std::fs::File::open(&path)
+                .unwrap_or_else(|e| panic!("failed opening trace {path}: {e}"));
+
+            trace::read_trace(std::io::BufReader::new(file))
+                .unwrap_or_else(|e| panic!("failed reading trace {path}: {e}"))
+        })
+        .collect()
+}
+
+fn write_history<O: Serialize>(
+    path: &std::path::Path,
+    history: &[Event<O>],
+) -> std::io::Result<()> {
+    let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+
+    for event in history {
+        serde_json::to_writer(&mut out, event)?;
+        out.write_all(b"\n")?;
+    }
+
+    out.flush()
+}
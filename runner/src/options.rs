@@ -0,0 +1,114 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+const USAGE: &str = "usage: runner -w <echo|unique-ids|broadcast|g-counter> --bin <path> \
+[--node-count <n>] [--rate <ops/s>] [--time-limit <secs>] [--timeout <secs>] \
+[--topology <grid|line|total>] [--history <path>]";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkloadKind {
+    Echo,
+    UniqueIds,
+    Broadcast,
+    GCounter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topology {
+    /// Nodes are arranged in a square grid, linked to their horizontal and
+    /// vertical neighbors
+    Grid,
+    /// Each node is linked to the nodes before and after it
+    Line,
+    /// Every node is linked to every other node
+    Total,
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub workload: WorkloadKind,
+    /// The node binary to run
+    pub bin: PathBuf,
+    pub node_count: usize,
+    /// Client operations invoked per second, across all clients
+    pub rate: f64,
+    pub time_limit: Duration,
+    /// How long a client waits for a reply before giving up on an operation
+    pub timeout: Duration,
+    pub topology: Topology,
+    /// Where to write the client history, for later checking
+    pub history: Option<PathBuf>,
+}
+
+impl Options {
+    /// Parses options from command line arguments, excluding the program name
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut workload = None;
+        let mut bin = None;
+        let mut options = Self {
+            workload: WorkloadKind::Echo,
+            bin: PathBuf::new(),
+            node_count: 1,
+            rate: 5.0,
+            time_limit: Duration::from_secs(10),
+            timeout: Duration::from_secs(1),
+            topology: Topology::Grid,
+            history: None,
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or(format!("missing value for {arg}\n{USAGE}"))
+            };
+
+            match arg.as_str() {
+                "-w" | "--workload" => {
+                    workload = Some(match value()?.as_str() {
+                        "echo" => WorkloadKind::Echo,
+                        "unique-ids" => WorkloadKind::UniqueIds,
+                        "broadcast" => WorkloadKind::Broadcast,
+                        "g-counter" => WorkloadKind::GCounter,
+                        other => return Err(format!("unknown workload {other}")),
+                    })
+                }
+                "--bin" => bin = Some(PathBuf::from(value()?)),
+                "--node-count" => options.node_count = parse(&arg, &value()?)?,
+                "--rate" => options.rate = parse(&arg, &value()?)?,
+                "--time-limit" => {
+                    options.time_limit = Duration::from_secs_f64(parse(&arg, &value()?)?)
+                }
+                "--timeout" => options.timeout = Duration::from_secs_f64(parse(&arg, &value()?)?),
+                "--topology" => {
+                    options.topology = match value()?.as_str() {
+                        "grid" => Topology::Grid,
+                        "line" => Topology::Line,
+                        "total" => Topology::Total,
+                        other => return Err(format!("unknown topology {other}")),
+                    }
+                }
+                "--history" => options.history = Some(PathBuf::from(value()?)),
+                "-h" | "--help" => return Err(USAGE.to_string()),
+                other => return Err(format!("unknown argument {other}\n{USAGE}")),
+            }
+        }
+
+        options.workload = workload.ok_or(format!("missing workload\n{USAGE}"))?;
+        options.bin = bin.ok_or(format!("missing node binary\n{USAGE}"))?;
+
+        if options.node_count == 0 {
+            return Err("node count must be positive".to_string());
+        }
+        if options.rate <= 0.0 {
+            return Err("rate must be positive".to_string());
+        }
+
+        Ok(options)
+    }
+}
+
+fn parse<T: std::str::FromStr>(arg: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {value} for {arg}"))
+}
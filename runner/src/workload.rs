@@ -0,0 +1,270 @@
+//! Client workloads driven against a cluster, recording a history of every
+//! operation for the checker.
+
+mod broadcast;
+mod echo;
+mod g_counter;
+mod unique_ids;
+
+use std::time::Duration;
+
+use checker::history::{Event, EventKind, HistoryError, Process};
+use common::message::{Message, MessageId};
+use common::node::NodeId;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::time::{Instant, MissedTickBehavior};
+
+use crate::cluster::Cluster;
+use crate::options::Options;
+
+pub use broadcast::Broadcast;
+pub use echo::Echo;
+pub use g_counter::GCounter;
+pub use unique_ids::UniqueIds;
+
+/// How long after the last operation final reads are issued, giving nodes a
+/// chance to converge
+const FINAL_READ_DELAY: Duration = Duration::from_secs(3);
+
+/// Error codes after which an operation may or may not have taken effect
+const INDEFINITE_ERRORS: [u64; 2] = [0, 13];
+
+pub trait Workload {
+    type Op: Clone + Serialize;
+
+    /// A request sent to every node before the workload starts, such as a
+    /// topology
+    fn setup(&self, _node_ids: &[NodeId]) -> Option<Value> {
+        None
+    }
+
+    /// Generates the next operation and the request payload that performs it
+    fn invoke(&mut self, rng: &mut StdRng) -> (Self::Op, Value);
+
+    /// Completes `invocation` with the payload of its reply, or returns `None`
+    /// if the reply is not one the operation expects
+    fn complete(&self, invocation: &Self::Op, reply: &Value) -> Option<Self::Op>;
+
+    /// The operation each client performs once the workload has finished and
+    /// nodes have had time to converge
+    fn final_op(&self) -> Option<(Self::Op, Value)> {
+        None
+    }
+
+    /// Describes every anomaly in `history`, recorded against a cluster of
+    /// `node_ids`
+    fn check(
+        &self,
+        history: &[Event<Self::Op>],
+        node_ids: &[NodeId],
+    ) -> Result<Vec<String>, HistoryError>;
+}
+
+/// An operation awaiting its reply
+struct InFlight<O> {
+    msg_id: MessageId,
+    op: O,
+    deadline: Instant,
+}
+
+/// A client performing one operation at a time against a single node
+struct Client<O> {
+    process: Process,
+    node: NodeId,
+    in_flight: Option<InFlight<O>>,
+}
+
+impl<O> Client<O> {
+    fn id(&self) -> String {
+        format!("c{}", self.process + 1)
+    }
+}
+
+struct Driver<O> {
+    clients: Vec<Client<O>>,
+    /// Assigned to a client once its operation's outcome becomes unknown, as
+    /// a process may only have one operation in flight
+    next_process: Process,
+    history: Vec<Event<O>>,
+    start: Instant,
+    timeout: Duration,
+}
+
+impl<O: Clone> Driver<O> {
+    fn time(&self) -> u64 {
+        self.start.elapsed().as_nanos() as u64
+    }
+
+    /// Records an event of the current process of `client`
+    fn record(&mut self, client: usize, kind: EventKind, op: O) {
+        let time = self.time();
+        let Client { process, node, .. } = &self.clients[client];
+        self.history.push(Event {
+            process: *process,
+            kind,
+            op,
+            node: Some(node.clone()),
+            time,
+        });
+    }
+
+    fn invoke(&mut self, cluster: &mut Cluster, client: usize, op: O, payload: Value) {
+        let (id, node) = (self.clients[client].id(), self.clients[client].node.clone());
+        let msg_id = cluster.send(&id, &node, payload);
+
+        self.record(client, EventKind::Invoke, op.clone());
+        self.clients[client].in_flight = Some(InFlight {
+            msg_id,
+            op,
+            deadline: Instant::now() + self.timeout,
+        });
+    }
+
+    /// Records the outcome of the operation `message` replies to, if it is
+    /// still in flight
+    fn complete<W: Workload<Op = O>>(&mut self, workload: &W, message: Message<Value>) {
+        let Some(client) = self.clients.iter().position(|client| {
+            matches!(&client.in_flight, Some(f) if Some(f.msg_id) == message.body.in_reply_to)
+        }) else {
+            return;
+        };
+
+        let in_flight = self.clients[client].in_flight.take().expect("in flight");
+        let reply = &message.body.payload;
+
+        if reply["type"] == "error" {
+            let indefinite = reply["code"]
+                .as_u64()
+                .is_some_and(|code| INDEFINITE_ERRORS.contains(&code));
+
+            if indefinite {
+                self.lose(client, in_flight.op);
+            } else {
+                self.record(client, EventKind::Fail, in_flight.op);
+            }
+            return;
+        }
+
+        match workload.complete(&in_flight.op, reply) {
+            Some(op) => self.record(client, EventKind::Ok, op),
+            None => {
+                eprintln!("{} sent unexpected reply: {reply}", message.src);
+                self.lose(client, in_flight.op);
+            }
+        }
+    }
+
+    /// Records that the outcome of `op` is unknown and moves `client` on to a
+    /// fresh process
+    fn lose(&mut self, client: usize, op: O) {
+        self.record(client, EventKind::Info, op);
+        self.clients[client].process = self.next_process;
+        self.next_process += 1;
+    }
+
+    /// Gives up on every operation whose reply is overdue
+    fn expire(&mut self) {
+        let now = Instant::now();
+
+        for client in 0..self.clients.len() {
+            if matches!(&self.clients[client].in_flight, Some(f) if f.deadline <= now) {
+                let in_flight = self.clients[client].in_flight.take().expect("in flight");
+                self.lose(client, in_flight.op);
+            }
+        }
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        self.clients
+            .iter()
+            .filter_map(|client| client.in_flight.as_ref().map(|f| f.deadline))
+            .min()
+    }
+
+    /// Waits for every in-flight operation to complete or time out
+    async fn settle<W: Workload<Op = O>>(&mut self, workload: &W, cluster: &mut Cluster) {
+        while let Some(deadline) = self.next_deadline() {
+            tokio::select! {
+                message = cluster.recv() => self.complete(workload, message),
+                _ = tokio::time::sleep_until(deadline) => self.expire(),
+            }
+        }
+    }
+}
+
+/// Drives `workload` against `cluster` with one client per node, invoking
+/// operations at the configured rate until the time limit, and returns the
+/// recorded history
+pub async fn run<W: Workload>(
+    workload: &mut W,
+    cluster: &mut Cluster,
+    options: &Options,
+) -> Vec<Event<W::Op>> {
+    let node_ids = cluster.node_ids().to_vec();
+
+    if let Some(payload) = workload.setup(&node_ids) {
+        for node_id in &node_ids {
+            cluster.setup(node_id, payload.clone()).await;
+        }
+    }
+
+    cluster.reset_stats();
+    let mut rng = StdRng::from_entropy();
+    let mut driver = Driver {
+        clients: node_ids
+            .iter()
+            .enumerate()
+            .map(|(process, node)| Client {
+                process: process as Process,
+                node: node.clone(),
+                in_flight: None,
+            })
+            .collect(),
+        next_process: node_ids.len() as Process,
+        history: vec![],
+        start: Instant::now(),
+        timeout: options.timeout,
+    };
+
+    let end = driver.start + options.time_limit;
+    let mut ticks = tokio::time::interval(Duration::from_secs_f64(1.0 / options.rate));
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep_until(end) => break,
+            _ = ticks.tick() => {
+                driver.expire();
+
+                let idle: Vec<_> = (0..driver.clients.len())
+                    .filter(|&client| driver.clients[client].in_flight.is_none())
+                    .collect();
+
+                if let Some(&client) = idle.choose(&mut rng) {
+                    let (op, payload) = workload.invoke(&mut rng);
+                    driver.invoke(cluster, client, op, payload);
+                }
+            }
+            message = cluster.recv() => driver.complete(workload, message),
+        }
+    }
+
+    driver.settle(workload, cluster).await;
+
+    if workload.final_op().is_some() {
+        tokio::time::sleep(FINAL_READ_DELAY).await;
+
+        for client in 0..driver.clients.len() {
+            let (op, payload) = workload.final_op().expect("final op");
+            driver.invoke(cluster, client, op, payload);
+        }
+
+        driver.settle(workload, cluster).await;
+    }
+
+    driver.history
+}
@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+
+use checker::broadcast::{self, BroadcastOp};
+use checker::history::{Event, HistoryError};
+use common::node::NodeId;
+use rand::rngs::StdRng;
+use rand::Rng;
+use serde_json::{json, Value};
+
+use super::Workload;
+use crate::options::Topology;
+
+/// Broadcasts unique integers and reads them back, expecting final reads to
+/// contain every acknowledged broadcast
+#[derive(Debug)]
+pub struct Broadcast {
+    topology: Topology,
+    next_message: u64,
+}
+
+impl Broadcast {
+    pub fn new(topology: Topology) -> Self {
+        Self {
+            topology,
+            next_message: 0,
+        }
+    }
+}
+
+impl Workload for Broadcast {
+    type Op = BroadcastOp;
+
+    fn setup(&self, node_ids: &[NodeId]) -> Option<Value> {
+        Some(json!({ "type": "topology", "topology": neighbors(self.topology, node_ids) }))
+    }
+
+    fn invoke(&mut self, rng: &mut StdRng) -> (Self::Op, Value) {
+        if rng.gen_bool(0.5) {
+            let message = json!(self.next_message);
+            self.next_message += 1;
+
+            (
+                BroadcastOp::Broadcast {
+                    message: message.clone(),
+                },
+                json!({ "type": "broadcast", "message": message }),
+            )
+        } else {
+            read()
+        }
+    }
+
+    fn complete(&self, invocation: &Self::Op, reply: &Value) -> Option<Self::Op> {
+        match invocation {
+            BroadcastOp::Broadcast { .. } => {
+                (reply["type"] == "broadcast_ok").then(|| invocation.clone())
+            }
+            BroadcastOp::Read { .. } => (reply["type"] == "read_ok").then(|| BroadcastOp::Read {
+                messages: reply["messages"].as_array().cloned(),
+            }),
+        }
+    }
+
+    fn final_op(&self) -> Option<(Self::Op, Value)> {
+        Some(read())
+    }
+
+    fn check(
+        &self,
+        history: &[Event<Self::Op>],
+        node_ids: &[NodeId],
+    ) -> Result<Vec<String>, HistoryError> {
+        Ok(broadcast::check(history, node_ids)?
+            .iter()
+            .map(|anomaly| format!("{anomaly:?}"))
+            .collect())
+    }
+}
+
+fn read() -> (BroadcastOp, Value) {
+    (
+        BroadcastOp::Read { messages: None },
+        json!({ "type": "read" }),
+    )
+}
+
+/// The neighbors of every node in `node_ids` when arranged in `topology`
+fn neighbors(topology: Topology, node_ids: &[NodeId]) -> HashMap<NodeId, Vec<NodeId>> {
+    let n = node_ids.len();
+    let side = (1..=n).find(|side| side * side >= n).unwrap_or(1);
+
+    (0..n)
+        .map(|i| {
+            let indices: Vec<usize> = match topology {
+                Topology::Grid => {
+                    let mut indices = vec![];
+                    if i % side > 0 {
+                        indices.push(i - 1);
+                    }
+                    if i % side + 1 < side && i + 1 < n {
+                        indices.push(i + 1);
+                    }
+                    if i >= side {
+                        indices.push(i - side);
+                    }
+                    if i + side < n {
+                        indices.push(i + side);
+                    }
+                    indices
+                }
+                Topology::Line => [i.checked_sub(1), Some(i + 1).filter(|&j| j < n)]
+                    .into_iter()
+                    .flatten()
+                    .collect(),
+                Topology::Total => (0..n).filter(|&j| j != i).collect(),
+            };
+
+            (
+                node_ids[i].clone(),
+                indices.into_iter().map(|j| node_ids[j].clone()).collect(),
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,66 @@
+use checker::history::{Event, EventKind, HistoryError};
+use common::node::NodeId;
+use rand::rngs::StdRng;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::Workload;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "f", rename_all = "snake_case")]
+pub enum EchoOp {
+    /// An echo, whose `reply` is only known once it completes
+    Echo {
+        echo: String,
+        #[serde(default)]
+        reply: Option<String>,
+    },
+}
+
+/// Sends random strings and expects them echoed back unchanged
+#[derive(Debug, Default)]
+pub struct Echo;
+
+impl Workload for Echo {
+    type Op = EchoOp;
+
+    fn invoke(&mut self, rng: &mut StdRng) -> (Self::Op, Value) {
+        let echo = format!("Please echo {}", rng.gen_range(0..128));
+
+        (
+            EchoOp::Echo {
+                echo: echo.clone(),
+                reply: None,
+            },
+            json!({ "type": "echo", "echo": echo }),
+        )
+    }
+
+    fn complete(&self, invocation: &Self::Op, reply: &Value) -> Option<Self::Op> {
+        let EchoOp::Echo { echo, .. } = invocation;
+
+        (reply["type"] == "echo_ok").then(|| EchoOp::Echo {
+            echo: echo.clone(),
+            reply: reply["echo"].as_str().map(String::from),
+        })
+    }
+
+    fn check(
+        &self,
+        history: &[Event<Self::Op>],
+        _node_ids: &[NodeId],
+    ) -> Result<Vec<String>, HistoryError> {
+        Ok(history
+            .iter()
+            .filter(|event| event.kind == EventKind::Ok)
+            .filter_map(|event| match &event.op {
+                EchoOp::Echo { echo, reply } if reply.as_ref() != Some(echo) => Some(format!(
+                    "process {} sent {echo:?} but received {reply:?}",
+                    event.process
+                )),
+                _ => None,
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,60 @@
+use checker::g_counter::{self, CounterOp};
+use checker::history::{Event, HistoryError};
+use common::node::NodeId;
+use rand::rngs::StdRng;
+use rand::Rng;
+use serde_json::{json, Value};
+
+use super::Workload;
+
+/// The largest delta a single add contributes
+const MAX_DELTA: u64 = 5;
+
+/// Adds small deltas to a counter and reads it, expecting final reads to
+/// reflect every acknowledged add
+#[derive(Debug, Default)]
+pub struct GCounter;
+
+impl Workload for GCounter {
+    type Op = CounterOp;
+
+    fn invoke(&mut self, rng: &mut StdRng) -> (Self::Op, Value) {
+        if rng.gen_bool(0.5) {
+            let delta = rng.gen_range(1..=MAX_DELTA);
+            (
+                CounterOp::Add { delta },
+                json!({ "type": "add", "delta": delta }),
+            )
+        } else {
+            read()
+        }
+    }
+
+    fn complete(&self, invocation: &Self::Op, reply: &Value) -> Option<Self::Op> {
+        match invocation {
+            CounterOp::Add { .. } => (reply["type"] == "add_ok").then(|| invocation.clone()),
+            CounterOp::Read { .. } => (reply["type"] == "read_ok").then(|| CounterOp::Read {
+                value: reply["value"].as_u64(),
+            }),
+        }
+    }
+
+    fn final_op(&self) -> Option<(Self::Op, Value)> {
+        Some(read())
+    }
+
+    fn check(
+        &self,
+        history: &[Event<Self::Op>],
+        node_ids: &[NodeId],
+    ) -> Result<Vec<String>, HistoryError> {
+        Ok(g_counter::check(history, node_ids)?
+            .iter()
+            .map(|anomaly| format!("{anomaly:?}"))
+            .collect())
+    }
+}
+
+fn read() -> (CounterOp, Value) {
+    (CounterOp::Read { value: None }, json!({ "type": "read" }))
+}
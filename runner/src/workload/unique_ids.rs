@@ -0,0 +1,39 @@
+use checker::history::{Event, HistoryError};
+use checker::unique_ids::{self, GenerateOp};
+use common::node::NodeId;
+use rand::rngs::StdRng;
+use serde_json::{json, Value};
+
+use super::Workload;
+
+/// Generates IDs and expects every one of them to be unique
+#[derive(Debug, Default)]
+pub struct UniqueIds;
+
+impl Workload for UniqueIds {
+    type Op = GenerateOp;
+
+    fn invoke(&mut self, _rng: &mut StdRng) -> (Self::Op, Value) {
+        (
+            GenerateOp::Generate { id: None },
+            json!({ "type": "generate" }),
+        )
+    }
+
+    fn complete(&self, _invocation: &Self::Op, reply: &Value) -> Option<Self::Op> {
+        (reply["type"] == "generate_ok").then(|| GenerateOp::Generate {
+            id: reply.get("id").cloned(),
+        })
+    }
+
+    fn check(
+        &self,
+        history: &[Event<Self::Op>],
+        _node_ids: &[NodeId],
+    ) -> Result<Vec<String>, HistoryError> {
+        Ok(unique_ids::check(history)?
+            .iter()
+            .map(|anomaly| format!("{anomaly:?}"))
+            .collect())
+    }
+}
@@ -0,0 +1,69 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use runner::options::{Options, Topology, WorkloadKind};
+
+fn parse(args: &[&str]) -> Result<Options, String> {
+    Options::parse(args.iter().map(|arg| arg.to_string()))
+}
+
+#[test]
+fn unset_options_take_their_defaults() {
+    let options = parse(&["-w", "echo", "--bin", "target/debug/echo"]).unwrap();
+
+    assert_eq!(options.workload, WorkloadKind::Echo);
+    assert_eq!(options.bin, PathBuf::from("target/debug/echo"));
+    assert_eq!(options.node_count, 1);
+    assert_eq!(options.rate, 5.0);
+    assert_eq!(options.time_limit, Duration::from_secs(10));
+    assert_eq!(options.timeout, Duration::from_secs(1));
+    assert_eq!(options.topology, Topology::Grid);
+    assert_eq!(options.history, None);
+}
+
+#[test]
+fn every_option_can_be_set() {
+    let options = parse(&[
+        "--workload",
+        "broadcast",
+        "--bin",
+        "broadcast",
+        "--node-count",
+        "5",
+        "--rate",
+        "12.5",
+        "--time-limit",
+        "2.5",
+        "--timeout",
+        "0.5",
+        "--topology",
+        "line",
+        "--history",
+        "history.jsonl",
+    ])
+    .unwrap();
+
+    assert_eq!(options.workload, WorkloadKind::Broadcast);
+    assert_eq!(options.node_count, 5);
+    assert_eq!(options.rate, 12.5);
+    assert_eq!(options.time_limit, Duration::from_millis(2500));
+    assert_eq!(options.timeout, Duration::from_millis(500));
+    assert_eq!(options.topology, Topology::Line);
+    assert_eq!(options.history, Some(PathBuf::from("history.jsonl")));
+}
+
+#[test]
+fn invalid_arguments_are_rejected() {
+    let error = |args: &[&str]| parse(args).unwrap_err();
+
+    assert!(error(&["--bin", "echo"]).starts_with("missing workload"));
+    assert!(error(&["-w", "echo"]).starts_with("missing node binary"));
+    assert!(error(&["-w", "lin-kv", "--bin", "echo"]).starts_with("unknown workload lin-kv"));
+    assert!(error(&["-w", "echo", "--bin"]).starts_with("missing value for --bin"));
+    assert!(error(&["-w", "echo", "--bin", "echo", "--rate", "fast"])
+        .starts_with("invalid value fast for --rate"));
+    assert!(error(&["-w", "echo", "--bin", "echo", "--node-count", "0"])
+        .starts_with("node count must be positive"));
+    assert!(error(&["-w", "echo", "--bin", "echo", "--verbose"])
+        .starts_with("unknown argument --verbose"));
+}
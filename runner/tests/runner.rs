@@ -0,0 +1,62 @@
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::process::Command;
+
+use checker::history::{self, Event, EventKind};
+use serde_json::Value;
+
+/// Builds the echo node, returning the path of its binary
+fn build_echo() -> PathBuf {
+    let output = Command::new(env!("CARGO"))
+        .args(["build", "-p", "echo", "--message-format=json"])
+        .output()
+        .expect("failed running cargo");
+    assert!(output.status.success(), "failed building echo");
+
+    output
+        .stdout
+        .split(|&byte| byte == b'\n')
+        .filter_map(|line| serde_json::from_slice::<Value>(line).ok())
+        .find(|message| message["target"]["name"] == "echo")
+        .and_then(|message| message["executable"].as_str().map(PathBuf::from))
+        .expect("cargo built no echo binary")
+}
+
+#[test]
+fn echo_workload_runs_against_the_echo_binary() {
+    let echo = build_echo();
+    let history = std::env::temp_dir().join(format!("runner-echo-{}.jsonl", std::process::id()));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_runner"))
+        .args([
+            "-w",
+            "echo",
+            "--node-count",
+            "3",
+            "--rate",
+            "50",
+            "--time-limit",
+            "1",
+        ])
+        .arg("--bin")
+        .arg(&echo)
+        .arg("--history")
+        .arg(&history)
+        .output()
+        .expect("failed running runner");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success(), "runner failed:\n{stdout}");
+    assert!(stdout.lines().any(|line| line == "valid"), "{stdout}");
+    assert!(stdout.contains(", 0 failed, 0 unknown"), "{stdout}");
+
+    let file = std::fs::File::open(&history).expect("failed opening history");
+    let events: Vec<Event<Value>> =
+        history::read_jsonl(BufReader::new(file)).expect("failed reading history");
+    let _ = std::fs::remove_file(&history);
+    let invocations = events
+        .iter()
+        .filter(|event| event.kind == EventKind::Invoke)
+        .count();
+    assert!(invocations > 0, "no operations were invoked");
+}
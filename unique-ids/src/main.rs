@@ -1,12 +1,11 @@
-use common::message::{Message, MessageBody, MessageId};
-use common::node::{Node, NodeId};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use common::message::{MaelstromPayload, Message, MessageBody, MessageId};
+use common::node::{ConcurrentNode, NodeId};
+use common::outbox::Outbox;
 use common::runtime::Runtime;
-use serde::{Deserialize, Serialize};
-use tokio::sync::mpsc::UnboundedSender;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(tag = "type")]
-#[serde(rename_all = "snake_case")]
+#[derive(Debug, Clone, MaelstromPayload)]
 enum MessagePayload {
     Generate,
     GenerateOk { id: String },
@@ -14,14 +13,16 @@ enum MessagePayload {
 
 struct UniqueIdNode {
     id: NodeId,
-    curr_msg_id: MessageId,
-    tx: UnboundedSender<Message<MessagePayload>>,
+    curr_msg_id: AtomicU64,
+    tx: Outbox<MessagePayload>,
 }
 
-impl<'de> Node<'de> for UniqueIdNode {
+/// Every request is independent, so requests are handled concurrently
+impl ConcurrentNode for UniqueIdNode {
     type Payload = MessagePayload;
+    type Output = MessagePayload;
 
-    fn handle_message(&mut self, message: Message<Self::Payload>) {
+    fn handle_message(&self, message: Message<Self::Payload>) {
         match message.body.payload {
             MessagePayload::Generate => {
                 let msg_id = self.next_msg_id();
@@ -30,27 +31,21 @@ impl<'de> Node<'de> for UniqueIdNode {
                 // the node ID to create a globally unique ID in the cluster
                 let id = format!("{}-{}", self.id, msg_id);
 
-                self.tx
-                    .send(Message {
-                        src: self.id.clone(),
-                        dest: message.src,
-                        body: MessageBody {
-                            msg_id: Some(msg_id),
-                            in_reply_to: message.body.msg_id,
-                            payload: MessagePayload::GenerateOk { id },
-                        },
-                    })
-                    .expect("failed sending generate reply");
+                self.tx.send_or_drop(Message {
+                    src: self.id.clone(),
+                    dest: message.src,
+                    body: MessageBody {
+                        msg_id: Some(msg_id),
+                        in_reply_to: message.body.msg_id,
+                        payload: MessagePayload::GenerateOk { id },
+                    },
+                });
             }
             MessagePayload::GenerateOk { .. } => {}
         }
     }
 
-    fn from_init(
-        node_id: NodeId,
-        _neighbors: Vec<NodeId>,
-        tx: tokio::sync::mpsc::UnboundedSender<Message<MessagePayload>>,
-    ) -> Self {
+    fn from_init(node_id: NodeId, _neighbors: Vec<NodeId>, tx: Outbox<MessagePayload>) -> Self {
         Self {
             id: node_id,
             curr_msg_id: Default::default(),
@@ -58,9 +53,9 @@ impl<'de> Node<'de> for UniqueIdNode {
         }
     }
 
-    fn next_msg_id(&mut self) -> MessageId {
-        self.curr_msg_id = self.curr_msg_id.checked_add(1).expect("ids exhausted");
-        self.curr_msg_id
+    fn next_msg_id(&self) -> MessageId {
+        let prev = self.curr_msg_id.fetch_add(1, Ordering::Relaxed);
+        prev.checked_add(1).expect("ids exhausted")
     }
 }
 
@@ -69,5 +64,5 @@ async fn main() {
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
 
-    Runtime::start::<UniqueIdNode, _, _>(stdin, stdout).await;
+    Runtime::start_concurrent::<UniqueIdNode, _, _>(stdin, stdout).await;
 }